@@ -1 +1,2 @@
 #include "ChakraCore.h"
+#include "ChakraCoreVersion.h"
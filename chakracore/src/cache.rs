@@ -0,0 +1,376 @@
+use crate::context::JsScriptContext;
+use crate::error::JsError;
+use crate::runtime::JsRuntime;
+use crate::script::{JsCompiledScript, JsParseOptions, JsScript};
+use crate::serialized::JsSerializedScript;
+use crate::value::JsValue;
+use chakracore_sys::{
+    CHAKRA_CORE_MAJOR_VERSION, CHAKRA_CORE_MINOR_VERSION, CHAKRA_CORE_PATCH_VERSION,
+    CHAKRA_CORE_VERSION_PRERELEASE, CHAKRA_CORE_VERSION_RELEASE,
+};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Identifies files written by the cache, followed by the hash of the key and the length of the
+/// source.
+const MAGIC: &[u8; 4] = b"JSBC";
+
+/// Distinguishes the temporary files written by the threads of this process.
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+/// The kind of data stored by a `ScriptCache`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScriptCacheFormat {
+    /// The bytecode produced by `JsScript::serialize`. The source is only loaded if the engine
+    /// needs it.
+    Bytecode,
+
+    /// The parser state produced by `JsScript::serialize_parser_state`. The source is still parsed,
+    /// but the initial scan is skipped.
+    ParserState,
+}
+
+impl ScriptCacheFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ScriptCacheFormat::Bytecode => "jsbc",
+            ScriptCacheFormat::ParserState => "jsps",
+        }
+    }
+}
+
+/// A directory of serialized scripts, keyed by a SHA-256 hash of the source, the URL, the parse
+/// options of the runtime and the engine version. Entries store the hash and the length of the
+/// source, so an entry is only used for the same script.
+///
+/// Scripts are executed from the cache when possible. A missing, corrupted or stale entry is
+/// replaced by serializing the source again, so the cache never has to be cleared manually.
+/// Failing to write the cache is not an error.
+#[derive(Clone, Debug)]
+pub struct ScriptCache {
+    directory: PathBuf,
+    format: ScriptCacheFormat,
+}
+
+impl ScriptCache {
+    /// Create a bytecode cache in `directory`, creating the directory if needed.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> io::Result<Self> {
+        ScriptCache::with_format(directory, ScriptCacheFormat::Bytecode)
+    }
+
+    /// Create a cache storing `format` in `directory`, creating the directory if needed.
+    pub fn with_format<P: Into<PathBuf>>(
+        directory: P,
+        format: ScriptCacheFormat,
+    ) -> io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self { directory, format })
+    }
+
+    /// The directory the cache is stored in.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Runs a script, using the cache if available.
+    pub fn run(
+        &self,
+        runtime: &mut JsRuntime,
+        url: &str,
+        source: &str,
+    ) -> Result<JsValue, JsError> {
+        let entry = self.entry(url, source, runtime.state.parse_options);
+
+        if self.format == ScriptCacheFormat::ParserState {
+            let script = JsScript::new(url, source)?;
+            if let Some(parser_state) = entry.read() {
+                match runtime.run_script_with_parser_state(&script, &parser_state) {
+                    Err(JsError::BadSerializedScript) => {}
+                    result => return result,
+                }
+            }
+
+            let parser_state = script.serialize_parser_state()?;
+            entry.write(&parser_state);
+            return runtime.run_script_with_parser_state(&script, &parser_state);
+        }
+
+        if let Some(buffer) = entry.read() {
+            let script = JsSerializedScript::new(buffer, JsScript::new(url, source)?)?;
+            match runtime.run_serialized(script) {
+                Err(JsError::BadSerializedScript) => {}
+                result => return result,
+            }
+        }
+
+        let script = JsScript::new(url, source)?;
+        let buffer = script.serialize()?;
+        entry.write(&buffer);
+        runtime.run_serialized(JsSerializedScript::new(buffer, script)?)
+    }
+
+    /// Compiles a script, using the cached bytecode if available. Parser states can't be compiled,
+    /// so a `ScriptCacheFormat::ParserState` cache compiles the source.
+    ///
+    /// The context must be the current context.
+    pub fn compile(
+        &self,
+        context: &JsScriptContext,
+        url: &str,
+        source: &str,
+    ) -> Result<JsCompiledScript, JsError> {
+        if self.format == ScriptCacheFormat::ParserState {
+            return JsScript::new(url, source)?.compile(context);
+        }
+
+        let entry = self.entry(url, source, context.runtime.parse_options);
+
+        if let Some(buffer) = entry.read() {
+            let script = JsSerializedScript::new(buffer, JsScript::new(url, source)?)?;
+            match script.compile(context) {
+                Err(JsError::BadSerializedScript) => {}
+                result => return result,
+            }
+        }
+
+        let script = JsScript::new(url, source)?;
+        let buffer = script.serialize()?;
+        entry.write(&buffer);
+        JsSerializedScript::new(buffer, script)?.compile(context)
+    }
+
+    fn entry(&self, url: &str, source: &str, options: JsParseOptions) -> CacheEntry {
+        let mut hasher = Sha256::new();
+        hasher.update(self.format.extension().as_bytes());
+        for version in [
+            CHAKRA_CORE_MAJOR_VERSION,
+            CHAKRA_CORE_MINOR_VERSION,
+            CHAKRA_CORE_PATCH_VERSION,
+            CHAKRA_CORE_VERSION_RELEASE,
+            CHAKRA_CORE_VERSION_PRERELEASE,
+        ] {
+            hasher.update(version.to_le_bytes());
+        }
+        // the source context only identifies the script to the debugger
+        hasher.update(options.attributes.bits().to_le_bytes());
+        hasher.update([u8::from(options.strict)]);
+        hasher.update((url.len() as u64).to_le_bytes());
+        hasher.update(url.as_bytes());
+        hasher.update(source.as_bytes());
+        let hash = hasher.finalize();
+
+        let mut header = Vec::with_capacity(MAGIC.len() + hash.len() + 8);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&hash);
+        header.extend_from_slice(&(source.len() as u64).to_le_bytes());
+
+        let name: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+        CacheEntry {
+            path: self
+                .directory
+                .join(format!("{}.{}", name, self.format.extension())),
+            header,
+        }
+    }
+}
+
+struct CacheEntry {
+    path: PathBuf,
+    header: Vec<u8>,
+}
+
+impl CacheEntry {
+    /// Reads the serialized script, ignoring entries that belong to another script.
+    fn read(&self) -> Option<Vec<u8>> {
+        let mut buffer = fs::read(&self.path).ok()?;
+        if buffer.len() <= self.header.len() || !buffer.starts_with(&self.header) {
+            return None;
+        }
+
+        Some(buffer.split_off(self.header.len()))
+    }
+
+    /// Writes the serialized script. The entry is replaced atomically so concurrent readers never
+    /// see a partial file.
+    fn write(&self, buffer: &[u8]) {
+        let mut contents = self.header.clone();
+        contents.extend_from_slice(buffer);
+
+        let temporary = self.path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        if fs::write(&temporary, contents).is_err() || fs::rename(&temporary, &self.path).is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::number::JsNumber;
+    use crate::string::JsString;
+
+    fn cache(name: &str) -> ScriptCache {
+        let directory =
+            std::env::temp_dir().join(format!("chakracore-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        ScriptCache::new(directory).unwrap()
+    }
+
+    fn entries(cache: &ScriptCache) -> Vec<PathBuf> {
+        fs::read_dir(cache.directory())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    #[test]
+    fn run_script_from_cache() {
+        let cache = cache("run");
+        let source = "(() => { return 40 + 2; })()";
+
+        for _ in 0..2 {
+            let mut runtime = JsRuntime::new().unwrap();
+            let mut context = JsScriptContext::new(&mut runtime).unwrap();
+            context.set_current_context().unwrap();
+
+            let result = cache.run(&mut runtime, "test", source).unwrap();
+            let result = JsNumber::try_from(result).unwrap();
+            assert_eq!(result.try_into(), Ok(42));
+        }
+
+        assert_eq!(entries(&cache).len(), 1);
+    }
+
+    #[test]
+    fn compile_script_from_cache() {
+        let cache = cache("compile");
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let first = cache.compile(&context, "test", "1 + 1").unwrap();
+        let second = cache.compile(&context, "test", "1 + 1").unwrap();
+
+        for compiled in [first, second] {
+            let result = JsNumber::try_from(compiled.run().unwrap()).unwrap();
+            assert_eq!(result.try_into(), Ok(2));
+        }
+    }
+
+    #[test]
+    fn run_script_from_parser_state_cache() {
+        let directory = cache("parser-state").directory().to_path_buf();
+        let cache = ScriptCache::with_format(directory, ScriptCacheFormat::ParserState).unwrap();
+        let source = "(() => { return 40 + 2; })()";
+
+        for _ in 0..2 {
+            let mut runtime = JsRuntime::new().unwrap();
+            let mut context = JsScriptContext::new(&mut runtime).unwrap();
+            context.set_current_context().unwrap();
+
+            let result = cache.run(&mut runtime, "test", source).unwrap();
+            let result = JsNumber::try_from(result).unwrap();
+            assert_eq!(result.try_into(), Ok(42));
+        }
+
+        let entries = entries(&cache);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].extension().unwrap(), "jsps");
+    }
+
+    #[test]
+    fn key_depends_on_url_and_source() {
+        let cache = cache("key");
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        cache.run(&mut runtime, "a", "1").unwrap();
+        cache.run(&mut runtime, "b", "1").unwrap();
+        cache.run(&mut runtime, "a", "2").unwrap();
+
+        assert_eq!(entries(&cache).len(), 3);
+    }
+
+    #[test]
+    fn key_depends_on_parse_options() {
+        let cache = cache("options");
+
+        for library_code in [false, true] {
+            let mut runtime = JsRuntime::builder()
+                .parse_options(JsParseOptions::new().library_code(library_code))
+                .build()
+                .unwrap();
+            let mut context = JsScriptContext::new(&mut runtime).unwrap();
+            context.set_current_context().unwrap();
+
+            cache.run(&mut runtime, "test", "1").unwrap();
+        }
+
+        assert_eq!(entries(&cache).len(), 2);
+    }
+
+    #[test]
+    fn replace_corrupted_entry() {
+        let cache = cache("corrupted");
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let source = "(() => { return 'cached'; })()";
+        cache.run(&mut runtime, "test", source).unwrap();
+
+        // keep the header but garble the serialized script
+        let path = entries(&cache).remove(0);
+        let mut contents = fs::read(&path).unwrap();
+        contents
+            .iter_mut()
+            .skip(
+                cache
+                    .entry("test", source, JsParseOptions::new())
+                    .header
+                    .len(),
+            )
+            .take(16)
+            .for_each(|byte| *byte = !*byte);
+        fs::write(&path, &contents).unwrap();
+
+        let result = cache.run(&mut runtime, "test", source).unwrap();
+        assert_eq!(
+            JsString::try_from(result).unwrap().to_string(),
+            Ok("cached".to_string())
+        );
+        assert_ne!(fs::read(&path).unwrap(), contents);
+    }
+
+    #[test]
+    fn ignore_entry_of_other_script() {
+        let cache = cache("collision");
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        cache.run(&mut runtime, "test", "'first'").unwrap();
+
+        // an entry found under the name of another script, as if their hashes collided
+        let first = cache.entry("test", "'first'", JsParseOptions::new());
+        let second = cache.entry("test", "'second'", JsParseOptions::new());
+        fs::copy(&first.path, &second.path).unwrap();
+
+        let result = cache.run(&mut runtime, "test", "'second'").unwrap();
+        assert_eq!(
+            JsString::try_from(result).unwrap().to_string(),
+            Ok("second".to_string())
+        );
+        assert!(second.read().is_some());
+    }
+}
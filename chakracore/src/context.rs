@@ -1,11 +1,134 @@
 use crate::error::JsError;
-use crate::runtime::JsRuntime;
-use chakracore_sys::{JsContextRef, JsCreateContext, JsSetCurrentContext};
+use crate::runtime::{JsRuntime, JsRuntimeState};
+use chakracore_sys::{
+    JsAddRef, JsContextRef, JsCreateContext, JsGetContextData, JsGetContextOfObject,
+    JsGetCurrentContext, JsRelease, JsSetContextData, JsSetCurrentContext, JsValueRef,
+};
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::ptr;
+use std::rc::Rc;
+
+thread_local! {
+    /// The contexts entered with `JsScriptContext::enter` on this thread, innermost last. Each
+    /// scope remembers the context that was current before it, so scopes can be left in any order.
+    static SCOPES: RefCell<Vec<ScopeEntry>> = const { RefCell::new(Vec::new()) };
+    static NEXT_SCOPE: Cell<usize> = const { Cell::new(0) };
+}
+
+struct ScopeEntry {
+    id: usize,
+    previous: JsContextRef,
+}
+
+pub(crate) fn current_context() -> Result<JsContextRef, JsError> {
+    let mut current = ptr::null_mut();
+    let res = unsafe { JsGetCurrentContext(&mut current) };
+    JsError::assert(res)?;
+    Ok(current)
+}
+
+/// Identifies a script context, e.g. the context owning an object.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct JsContextHandle {
+    pub(crate) handle: JsContextRef,
+}
+
+/// Runs `f` with the context owning `object` as the current context, restoring the current context
+/// afterwards. Values that don't belong to a context are used in the current context.
+pub(crate) fn with_owning_context<R, F>(object: JsValueRef, f: F) -> Result<R, JsError>
+where
+    F: FnOnce() -> Result<R, JsError>,
+{
+    let mut owner = ptr::null_mut();
+    let res = unsafe { JsGetContextOfObject(object, &mut owner) };
+    if JsError::assert(res).is_err() || owner.is_null() {
+        return f();
+    }
+
+    with_context(owner, f)
+}
+
+/// Runs `f` with `context` as the current context, restoring the current context afterwards.
+pub(crate) fn with_context<R, F>(context: JsContextRef, f: F) -> Result<R, JsError>
+where
+    F: FnOnce() -> Result<R, JsError>,
+{
+    let current = current_context()?;
+    if context == current {
+        return f();
+    }
+
+    JsError::assert(unsafe { JsSetCurrentContext(context) })?;
+    let _restore = RestoreContext(current);
+    f()
+}
+
+/// Makes a context current again when dropped, including when unwinding.
+struct RestoreContext(JsContextRef);
+
+impl Drop for RestoreContext {
+    fn drop(&mut self) {
+        // the result of `f` is what matters to the caller, so a failure is left unreported
+        unsafe { JsSetCurrentContext(self.0) };
+    }
+}
+
+/// Host data attached to a context, one value per type.
+#[derive(Default)]
+struct JsContextData {
+    values: RefCell<HashMap<TypeId, Rc<dyn Any>>>,
+}
+
+impl JsContextData {
+    fn get<T: 'static>(&self) -> Option<Rc<T>> {
+        let value = self.values.borrow().get(&TypeId::of::<T>())?.clone();
+        value.downcast().ok()
+    }
+}
+
+/// The host data attached to the current context.
+fn current_context_data() -> Option<&'static JsContextData> {
+    let context = current_context().ok()?;
+    if context.is_null() {
+        return None;
+    }
+
+    let mut data = ptr::null_mut();
+    JsError::assert(unsafe { JsGetContextData(context, &mut data) }).ok()?;
+    if data.is_null() {
+        return None;
+    }
+
+    // the data lives as long as the context, which is current
+    Some(unsafe { &*(data as *const JsContextData) })
+}
+
+/// The host data of type `T` attached to the current context.
+pub(crate) fn current_data<T: 'static>() -> Option<Rc<T>> {
+    current_context_data()?.get()
+}
+
+/// The host data of type `T` attached to the current context, attaching the default value if
+/// there is none yet. Used for the state the crate keeps per context.
+pub(crate) fn current_data_or_default<T: Default + 'static>() -> Option<Rc<T>> {
+    let data = current_context_data()?;
+    if let Some(value) = data.get() {
+        return Some(value);
+    }
+
+    let value = Rc::new(T::default());
+    data.values
+        .borrow_mut()
+        .insert(TypeId::of::<T>(), value.clone());
+    Some(value)
+}
 
 pub struct JsScriptContext {
     context: JsContextRef,
-    is_current_context: bool,
+    data: Box<JsContextData>,
+    pub(crate) runtime: Rc<JsRuntimeState>,
 }
 
 impl JsScriptContext {
@@ -15,39 +138,138 @@ impl JsScriptContext {
         let res = unsafe { JsCreateContext(runtime.handle, &mut context) };
         JsError::assert(res)?;
 
-        Ok(Self {
+        // the context is only referenced by the host, so it has to be kept alive explicitly
+        JsError::assert(unsafe { JsAddRef(context, ptr::null_mut()) })?;
+        let context = Self {
             context,
-            is_current_context: false,
-        })
+            data: Box::default(),
+            runtime: runtime.state.clone(),
+        };
+
+        runtime.state.attach(context.context)?;
+        let data = &*context.data as *const JsContextData as *mut _;
+        JsError::assert(unsafe { JsSetContextData(context.context, data) })?;
+
+        Ok(context)
+    }
+
+    /// Identifies the context, e.g. to compare it with `JsValue::owning_context`.
+    pub fn handle(&self) -> JsContextHandle {
+        JsContextHandle {
+            handle: self.context,
+        }
+    }
+
+    /// Attaches host data to the context, replacing any previous value of the same type. The data
+    /// is dropped with the context.
+    ///
+    /// Functions called from the context can get the data with `JsFunctionContext::data`.
+    pub fn set_data<T: 'static>(&mut self, value: T) {
+        self.data
+            .values
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), Rc::new(value));
+    }
+
+    /// The host data of type `T` attached to the context.
+    pub fn data<T: 'static>(&self) -> Option<&T> {
+        let value = self.data.get::<T>()?;
+
+        // values are only replaced through `set_data`, which can't be called while the returned
+        // reference borrows the context, so the map keeps the value alive
+        Some(unsafe { &*Rc::as_ptr(&value) })
+    }
+
+    /// Makes this the current script context on the thread until the returned scope is dropped,
+    /// at which point the previously current context is restored.
+    ///
+    /// Scopes can be nested, and left in any order.
+    pub fn enter(&self) -> Result<JsContextScope<'_>, JsError> {
+        let previous = current_context()?;
+        JsError::assert(unsafe { JsSetCurrentContext(self.context) })?;
+
+        let id = NEXT_SCOPE.with(|next| next.replace(next.get() + 1));
+        SCOPES.with(|scopes| scopes.borrow_mut().push(ScopeEntry { id, previous }));
+
+        Ok(JsContextScope { id, _context: self })
     }
 
     /// Sets the current script context on the thread.
     pub fn set_current_context(&mut self) -> Result<(), JsError> {
         let res = unsafe { JsSetCurrentContext(self.context) };
-        JsError::assert(res)?;
-        self.is_current_context = true;
-        Ok(())
+        JsError::assert(res)
     }
 
-    /// Clears the current script context on the thread.
+    /// Clears the current script context on the thread if this is the current context.
     ///
     /// This does not need to be explicitly called - it will automatically be called when the
-    /// context is dropped if it was set as the current context.
+    /// context is dropped.
     pub fn clear_current_context(&mut self) -> Result<(), JsError> {
-        if self.is_current_context {
+        if current_context()? == self.context {
             let res = unsafe { JsSetCurrentContext(std::ptr::null_mut()) };
             JsError::assert(res)?;
-            self.is_current_context = false;
         }
 
         Ok(())
     }
+
+    /// Checks that this is the current script context on the thread.
+    pub(crate) fn assert_current(&self) -> Result<(), JsError> {
+        let current = current_context()?;
+
+        if current.is_null() {
+            Err(JsError::NoCurrentContext)
+        } else if current != self.context {
+            Err(JsError::InvalidContext)
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl Drop for JsScriptContext {
     fn drop(&mut self) {
         self.clear_current_context()
             .expect("Failed to clear current context.");
+
+        // the context is gone with the runtime
+        if !self.runtime.disposed.get() {
+            unsafe {
+                JsSetContextData(self.context, ptr::null_mut());
+                JsRelease(self.context, ptr::null_mut());
+            }
+        }
+    }
+}
+
+/// A scope in which a script context is the current context, created by `JsScriptContext::enter`.
+#[must_use = "the context is left as soon as the scope is dropped"]
+pub struct JsContextScope<'a> {
+    id: usize,
+    _context: &'a JsScriptContext,
+}
+
+impl Drop for JsContextScope<'_> {
+    fn drop(&mut self) {
+        let restore = SCOPES.with(|scopes| {
+            let mut scopes = scopes.borrow_mut();
+            let index = scopes.iter().position(|entry| entry.id == self.id)?;
+            let entry = scopes.remove(index);
+
+            match scopes.get_mut(index) {
+                // a scope entered later is still active, so it restores our previous context
+                Some(next) => {
+                    next.previous = entry.previous;
+                    None
+                }
+                None => Some(entry.previous),
+            }
+        });
+
+        if let Some(previous) = restore {
+            let res = unsafe { JsSetCurrentContext(previous) };
+            JsError::assert(res).expect("Failed to restore the previous context.");
+        }
     }
 }
 
@@ -71,4 +293,131 @@ mod tests {
 
         assert!(res.is_ok());
     }
+
+    #[test]
+    fn enter_nested_contexts() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let first = JsScriptContext::new(&mut runtime).unwrap();
+        let second = JsScriptContext::new(&mut runtime).unwrap();
+
+        {
+            let _outer = first.enter().unwrap();
+            assert_eq!(first.assert_current(), Ok(()));
+
+            {
+                let _inner = second.enter().unwrap();
+                assert_eq!(second.assert_current(), Ok(()));
+            }
+
+            assert_eq!(first.assert_current(), Ok(()));
+        }
+
+        assert_eq!(first.assert_current(), Err(JsError::NoCurrentContext));
+    }
+
+    #[test]
+    fn restore_context_after_panic() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut first = JsScriptContext::new(&mut runtime).unwrap();
+        let second = JsScriptContext::new(&mut runtime).unwrap();
+        first.set_current_context().unwrap();
+
+        let result = std::panic::catch_unwind(|| {
+            with_context(second.context, || -> Result<(), JsError> {
+                panic!("f failed")
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(first.assert_current(), Ok(()));
+    }
+
+    #[test]
+    fn leave_interleaved_contexts() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut first = JsScriptContext::new(&mut runtime).unwrap();
+        let second = JsScriptContext::new(&mut runtime).unwrap();
+        let third = JsScriptContext::new(&mut runtime).unwrap();
+        first.set_current_context().unwrap();
+
+        let second_scope = second.enter().unwrap();
+        let third_scope = third.enter().unwrap();
+
+        // leaving the outer scope first keeps the inner context current
+        drop(second_scope);
+        assert_eq!(third.assert_current(), Ok(()));
+
+        // and the inner scope then restores the context from before both scopes
+        drop(third_scope);
+        assert_eq!(first.assert_current(), Ok(()));
+    }
+
+    #[test]
+    fn drop_context_keeps_other_current_context() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut first = JsScriptContext::new(&mut runtime).unwrap();
+        let mut second = JsScriptContext::new(&mut runtime).unwrap();
+
+        first.set_current_context().unwrap();
+        second.set_current_context().unwrap();
+        drop(first);
+
+        assert_eq!(second.assert_current(), Ok(()));
+        second.clear_current_context().unwrap();
+        assert_eq!(second.assert_current(), Err(JsError::NoCurrentContext));
+    }
+
+    #[test]
+    fn set_context_data() {
+        struct Tenant(&'static str);
+
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        assert!(context.data::<Tenant>().is_none());
+
+        context.set_data(Tenant("first"));
+        context.set_data(42_u32);
+        assert_eq!(
+            context.data::<Tenant>().map(|tenant| tenant.0),
+            Some("first")
+        );
+        assert_eq!(context.data::<u32>(), Some(&42));
+
+        context.set_data(Tenant("second"));
+        assert_eq!(
+            context.data::<Tenant>().map(|tenant| tenant.0),
+            Some("second")
+        );
+    }
+
+    #[test]
+    fn get_current_context_data() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut first = JsScriptContext::new(&mut runtime).unwrap();
+        let mut second = JsScriptContext::new(&mut runtime).unwrap();
+        first.set_data("first");
+        second.set_data("second");
+
+        assert!(current_data::<&str>().is_none());
+        {
+            let _scope = first.enter().unwrap();
+            assert_eq!(current_data::<&str>().as_deref(), Some(&"first"));
+
+            let _scope = second.enter().unwrap();
+            assert_eq!(current_data::<&str>().as_deref(), Some(&"second"));
+            assert!(current_data::<u32>().is_none());
+        }
+    }
+
+    #[test]
+    fn drop_context_data_with_context() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let value = Rc::new(());
+
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_data(value.clone());
+        assert_eq!(Rc::strong_count(&value), 2);
+
+        drop(context);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
 }
@@ -1,7 +1,8 @@
 use std::os::raw::c_uint;
 use thiserror::Error;
 
-#[derive(Error, Debug, Eq, PartialEq)]
+#[derive(Error, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum JsError {
     /// Category of errors that relates to incorrect usage of the API itself.
     #[error("Category of errors that relates to incorrect usage of the API itself.")]
@@ -179,6 +180,15 @@ pub enum JsError {
     #[error("JavaScript failed to compile.")]
     ScriptCompile,
 
+    /// JavaScript failed to compile because of a syntax error. The line and column are 1-based.
+    #[error("{message} ({url}:{line}:{column})")]
+    SyntaxError {
+        message: String,
+        url: String,
+        line: u32,
+        column: u32,
+    },
+
     /// A script was terminated due to a request to suspend a runtime.
     #[error("A script was terminated due to a request to suspend a runtime.")]
     ScriptTerminated,
@@ -228,62 +238,284 @@ pub enum JsError {
     /// VM was unable to perform the request action.
     #[error("VM was unable to perform the request action.")]
     DiagUnableToPerformAction,
+
+    /// A job queued by a promise threw an exception. The exception is converted to a string.
+    #[error("Uncaught exception in a promise job: {message}")]
+    JobException { message: String },
+
+    /// A promise was rejected without a handler while running a script on a runtime with the
+    /// `JsRejectionPolicy::Error` policy. The reason is converted to a string.
+    #[error("Unhandled promise rejection: {reason}")]
+    UnhandledRejection { reason: String },
+
+    /// A runtime was built with a thread service while the most thread services that can be told
+    /// apart are already in use.
+    #[error("Too many thread services are in use at once.")]
+    TooManyThreadServices,
+
+    /// An error code this crate doesn't know about.
+    #[error("Unknown error code {0:#x}.")]
+    Unknown(u32),
 }
 
+/// The broad kind of a `JsError`, given by the range of its error code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum JsErrorCategory {
+    /// Incorrect usage of the API itself.
+    Usage,
+
+    /// Errors occurring within the engine itself.
+    Engine,
+
+    /// Errors in a script.
+    Script,
+
+    /// Fatal errors signifying failure of the engine.
+    Fatal,
+
+    /// Failures during diagnostic operations.
+    Diag,
+
+    /// An error code outside of the known ranges.
+    Unknown,
+}
+
+/// Maps the error codes of the engine to errors and back. `JsError::SyntaxError` is reported as
+/// `ScriptCompile`, `JsError::JobException` and `JsError::UnhandledRejection` as `ScriptException`
+/// and `JsError::TooManyThreadServices` as `InvalidArgument`.
+macro_rules! error_codes {
+    ($($code:literal => $error:ident,)*) => {
+        /// The error for a code returned by the engine, if it's known.
+        fn from_code(code: c_uint) -> Option<JsError> {
+            match code {
+                $($code => Some(JsError::$error),)*
+                _ => None,
+            }
+        }
+
+        /// The code of an error the engine reports as is.
+        fn to_code(error: &JsError) -> Option<c_uint> {
+            match error {
+                $(JsError::$error => Some($code),)*
+                _ => None,
+            }
+        }
+
+        #[cfg(test)]
+        const ERROR_CODES: &[c_uint] = &[$($code),*];
+    };
+}
+
+error_codes! {
+    65536 => CategoryUsage,
+    65537 => InvalidArgument,
+    65538 => NullArgument,
+    65539 => NoCurrentContext,
+    65540 => InExceptionState,
+    65541 => NotImplemented,
+    65542 => WrongThread,
+    65543 => RuntimeInUse,
+    65544 => BadSerializedScript,
+    65545 => InDisabledState,
+    65546 => CannotDisableExecution,
+    65547 => HeapEnumInProgress,
+    65548 => ArgumentNotObject,
+    65549 => InProfileCallback,
+    65550 => InThreadServiceCallback,
+    65551 => CannotSerializeDebugScript,
+    65552 => AlreadyDebuggingContext,
+    65553 => AlreadyProfilingContext,
+    65554 => IdleNotEnabled,
+    65555 => CannotSetProjectionEnqueueCallback,
+    65556 => CannotStartProjection,
+    65557 => InObjectBeforeCollectCallback,
+    65558 => ObjectNotInspectable,
+    65559 => PropertyNotSymbol,
+    65560 => PropertyNotString,
+    65561 => InvalidContext,
+    65562 => InvalidModuleHostInfoKind,
+    65563 => ModuleParsed,
+    65564 => NoWeakRefRequired,
+    65565 => PromisePending,
+    65566 => ModuleNotEvaluated,
+    131_072 => CategoryEngine,
+    131_073 => OutOfMemory,
+    131_074 => BadFPUState,
+    196_608 => CategoryScript,
+    196_609 => ScriptException,
+    196_610 => ScriptCompile,
+    196_611 => ScriptTerminated,
+    196_612 => ScriptEvalDisabled,
+    262_144 => CategoryFatal,
+    262_145 => Fatal,
+    262_146 => WrongRuntime,
+    327_680 => CategoryDiagError,
+    327_681 => DiagAlreadyInDebugMode,
+    327_682 => DiagNotInDebugMode,
+    327_683 => DiagNotAtBreak,
+    327_684 => DiagInvalidHandle,
+    327_685 => DiagObjectNotFound,
+    327_686 => DiagUnableToPerformAction,
+}
+
+const INVALID_ARGUMENT: c_uint = 65537;
+const SCRIPT_EXCEPTION: c_uint = 196_609;
+const SCRIPT_COMPILE: c_uint = 196_610;
+
 impl JsError {
     pub fn assert(error_code: c_uint) -> Result<(), Self> {
-        match error_code {
-            0 => Ok(()),
-            65536 => Err(JsError::CategoryUsage),
-            65537 => Err(JsError::InvalidArgument),
-            65538 => Err(JsError::NullArgument),
-            65539 => Err(JsError::NoCurrentContext),
-            65540 => Err(JsError::InExceptionState),
-            65541 => Err(JsError::NotImplemented),
-            65542 => Err(JsError::WrongThread),
-            65543 => Err(JsError::RuntimeInUse),
-            65544 => Err(JsError::BadSerializedScript),
-            65545 => Err(JsError::InDisabledState),
-            65546 => Err(JsError::CannotDisableExecution),
-            65547 => Err(JsError::HeapEnumInProgress),
-            65548 => Err(JsError::ArgumentNotObject),
-            65549 => Err(JsError::InProfileCallback),
-            65550 => Err(JsError::InThreadServiceCallback),
-            65551 => Err(JsError::CannotSerializeDebugScript),
-            65552 => Err(JsError::AlreadyDebuggingContext),
-            65553 => Err(JsError::AlreadyProfilingContext),
-            65554 => Err(JsError::IdleNotEnabled),
-            65555 => Err(JsError::CannotSetProjectionEnqueueCallback),
-            65556 => Err(JsError::CannotStartProjection),
-            65557 => Err(JsError::InObjectBeforeCollectCallback),
-            65558 => Err(JsError::ObjectNotInspectable),
-            65559 => Err(JsError::PropertyNotSymbol),
-            65560 => Err(JsError::PropertyNotString),
-            65561 => Err(JsError::InvalidContext),
-            65562 => Err(JsError::InvalidModuleHostInfoKind),
-            65563 => Err(JsError::ModuleParsed),
-            65564 => Err(JsError::NoWeakRefRequired),
-            65565 => Err(JsError::PromisePending),
-            65566 => Err(JsError::ModuleNotEvaluated),
-            131_072 => Err(JsError::CategoryEngine),
-            131_073 => Err(JsError::OutOfMemory),
-            131_074 => Err(JsError::BadFPUState),
-            196_608 => Err(JsError::CategoryScript),
-            196_609 => Err(JsError::ScriptException),
-            196_610 => Err(JsError::ScriptCompile),
-            196_611 => Err(JsError::ScriptTerminated),
-            196_612 => Err(JsError::ScriptEvalDisabled),
-            262_144 => Err(JsError::CategoryFatal),
-            262_145 => Err(JsError::Fatal),
-            262_146 => Err(JsError::WrongRuntime),
-            327_680 => Err(JsError::CategoryDiagError),
-            327_681 => Err(JsError::DiagAlreadyInDebugMode),
-            327_682 => Err(JsError::DiagNotInDebugMode),
-            327_683 => Err(JsError::DiagNotAtBreak),
-            327_684 => Err(JsError::DiagInvalidHandle),
-            327_685 => Err(JsError::DiagObjectNotFound),
-            327_686 => Err(JsError::DiagUnableToPerformAction),
-            _ => unreachable!(),
+        if error_code == 0 {
+            return Ok(());
+        }
+
+        Err(from_code(error_code).unwrap_or(JsError::Unknown(error_code)))
+    }
+
+    /// The error code returned by the engine.
+    pub fn raw_code(&self) -> c_uint {
+        match self {
+            JsError::SyntaxError { .. } => SCRIPT_COMPILE,
+            JsError::JobException { .. } | JsError::UnhandledRejection { .. } => SCRIPT_EXCEPTION,
+            JsError::TooManyThreadServices => INVALID_ARGUMENT,
+            JsError::Unknown(code) => *code,
+            error => to_code(error).unwrap_or_default(),
+        }
+    }
+
+    /// The category of the error.
+    pub fn category(&self) -> JsErrorCategory {
+        match self.raw_code() >> 16 {
+            1 => JsErrorCategory::Usage,
+            2 => JsErrorCategory::Engine,
+            3 => JsErrorCategory::Script,
+            4 => JsErrorCategory::Fatal,
+            5 => JsErrorCategory::Diag,
+            _ => JsErrorCategory::Unknown,
         }
     }
+
+    /// Whether the runtime can still be used after the error. Fatal errors (and codes outside of
+    /// the known ranges) leave the engine in an unknown state, so the runtime should be disposed.
+    pub fn is_recoverable(&self) -> bool {
+        !matches!(
+            self.category(),
+            JsErrorCategory::Fatal | JsErrorCategory::Unknown
+        )
+    }
+}
+
+/// Errors loading the source of a script.
+#[derive(Error, Debug)]
+pub enum JsSourceError {
+    /// The source could not be read.
+    #[error("The source could not be read: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The source is not valid UTF-8.
+    #[error("The source is not valid UTF-8 (invalid byte at offset {offset}).")]
+    InvalidUtf8 { offset: usize },
+
+    /// The source is UTF-16, but has an odd number of bytes.
+    #[error("The source is UTF-16, but has an odd number of bytes.")]
+    InvalidUtf16,
+
+    /// The engine failed to create the script.
+    #[error(transparent)]
+    Js(#[from] JsError),
+}
+
+/// Errors loading or running an ES module.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum JsModuleError {
+    /// The runtime has no module loader.
+    #[error("The runtime has no module loader.")]
+    NoLoader,
+
+    /// The loader doesn't know the module.
+    #[error("Cannot find module '{specifier}'.")]
+    NotFound { specifier: String },
+
+    /// The module is outside of the directory the loader is allowed to load from.
+    #[error("Module '{specifier}' is outside of the allowed root directory.")]
+    OutsideRoot { specifier: String },
+
+    /// The source of the module could not be loaded.
+    #[error("Cannot load module '{specifier}': {source}")]
+    Source {
+        specifier: String,
+        source: JsSourceError,
+    },
+
+    /// The module or one of its imports failed to parse, link or evaluate. The exception is
+    /// converted to a string.
+    #[error("{0}")]
+    Exception(String),
+
+    /// The engine failed to load the module.
+    #[error(transparent)]
+    Js(#[from] JsError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_error_codes() {
+        assert_eq!(JsError::assert(0), Ok(()));
+        assert_eq!(JsError::assert(65537), Err(JsError::InvalidArgument));
+        assert_eq!(JsError::assert(0x30005), Err(JsError::Unknown(0x30005)));
+
+        for &code in ERROR_CODES {
+            assert_eq!(JsError::assert(code).unwrap_err().raw_code(), code);
+        }
+    }
+
+    #[test]
+    fn error_categories() {
+        assert_eq!(JsError::NoCurrentContext.category(), JsErrorCategory::Usage);
+        assert_eq!(JsError::OutOfMemory.category(), JsErrorCategory::Engine);
+        assert_eq!(JsError::ScriptException.category(), JsErrorCategory::Script);
+        assert_eq!(JsError::WrongRuntime.category(), JsErrorCategory::Fatal);
+        assert_eq!(JsError::DiagNotAtBreak.category(), JsErrorCategory::Diag);
+        assert_eq!(
+            JsError::TooManyThreadServices.category(),
+            JsErrorCategory::Usage
+        );
+        assert_eq!(
+            JsError::Unknown(0x30005).category(),
+            JsErrorCategory::Script
+        );
+        assert_eq!(
+            JsError::Unknown(0x90000).category(),
+            JsErrorCategory::Unknown
+        );
+
+        let syntax_error = JsError::SyntaxError {
+            message: "Expected ')'".to_string(),
+            url: "test".to_string(),
+            line: 1,
+            column: 1,
+        };
+        assert_eq!(syntax_error.raw_code(), 196_610);
+        assert_eq!(syntax_error.category(), JsErrorCategory::Script);
+
+        let rejection = JsError::UnhandledRejection {
+            reason: "Error: failed".to_string(),
+        };
+        assert_eq!(rejection.raw_code(), 196_609);
+        assert_eq!(rejection.category(), JsErrorCategory::Script);
+
+        let job = JsError::JobException {
+            message: "Error: failed".to_string(),
+        };
+        assert_eq!(job.raw_code(), 196_609);
+
+        assert!(syntax_error.is_recoverable());
+        assert!(JsError::OutOfMemory.is_recoverable());
+        assert!(!JsError::Fatal.is_recoverable());
+        assert!(!JsError::Unknown(0x90000).is_recoverable());
+    }
 }
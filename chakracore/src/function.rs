@@ -1,10 +1,16 @@
+use crate::context::current_data;
 use crate::error::JsError;
+use crate::panic::{catch_and_throw, throw_error};
+use crate::promise::JsPromise;
+use crate::runtime::JsRuntimeState;
 use crate::value::JsValue;
-use chakracore_sys::{JsCreateFunction, JsValueRef};
+use chakracore_sys::{JsAddRef, JsCreateFunction, JsNativeFunction, JsRelease, JsValueRef};
 use std::ffi::c_void;
+use std::future::Future;
 use std::marker::PhantomData;
 use std::os::raw::c_ushort;
 use std::ptr;
+use std::rc::Rc;
 
 unsafe extern "C" fn handler<T: Into<JsValue>>(
     _callee: JsValueRef, // TODO: what should we do with the callee?
@@ -15,7 +21,27 @@ unsafe extern "C" fn handler<T: Into<JsValue>>(
 ) -> JsValueRef {
     let context = JsFunctionContext::new(argument_count, arguments, is_construct_call);
     let closure = &mut *(callback_state as *mut Box<dyn FnMut(JsFunctionContext) -> T>);
-    closure(context).into().handle
+    catch_and_throw(|| closure(context).into().handle)
+}
+
+/// Like `handler`, for callbacks that fail with a `JsError`, which is thrown as a JS `Error`.
+unsafe extern "C" fn fallible_handler<T: Into<JsValue>>(
+    _callee: JsValueRef,
+    is_construct_call: bool,
+    arguments: *mut JsValueRef,
+    argument_count: c_ushort,
+    callback_state: *mut c_void,
+) -> JsValueRef {
+    let context = JsFunctionContext::new(argument_count, arguments, is_construct_call);
+    let closure =
+        &mut *(callback_state as *mut Box<dyn FnMut(JsFunctionContext) -> Result<T, JsError>>);
+    catch_and_throw(|| match closure(context) {
+        Ok(value) => value.into().handle,
+        Err(error) => {
+            throw_error(&error.to_string());
+            ptr::null_mut()
+        }
+    })
 }
 
 pub struct JsFunctionContext {
@@ -25,6 +51,12 @@ pub struct JsFunctionContext {
 }
 
 impl JsFunctionContext {
+    /// The host data of type `T` attached to the current context with
+    /// `JsScriptContext::set_data`.
+    pub fn data<T: 'static>(&self) -> Option<Rc<T>> {
+        current_data()
+    }
+
     fn new(argument_count: u16, arguments: *mut JsValueRef, is_construct_call: bool) -> Self {
         let mut args = Vec::new();
         for i in 0..argument_count as usize {
@@ -49,13 +81,19 @@ pub struct JsFunction<T: Into<JsValue>> {
 
 impl<T: Into<JsValue>> JsFunction<T> {
     pub fn new<'a>(callback: Box<dyn FnMut(JsFunctionContext) -> T + 'a>) -> Result<Self, JsError> {
+        JsFunction::create(callback, Some(handler::<T>))
+    }
+
+    /// Creates a function calling `callback` through `handler`, which must expect a `Box<C>` as
+    /// its callback state.
+    fn create<C: ?Sized>(callback: Box<C>, handler: JsNativeFunction) -> Result<Self, JsError> {
         let callback = Box::new(callback);
 
         // TODO: don't forget to drop this later
         let callback = Box::into_raw(callback);
 
         let mut func = ptr::null_mut();
-        let res = unsafe { JsCreateFunction(Some(handler::<T>), callback as *mut _, &mut func) };
+        let res = unsafe { JsCreateFunction(handler, callback as *mut _, &mut func) };
         JsError::assert(res)?;
 
         Ok(Self {
@@ -65,6 +103,73 @@ impl<T: Into<JsValue>> JsFunction<T> {
     }
 }
 
+impl JsFunction<JsPromise> {
+    /// Creates a function returning a promise, settled with the output of the future returned by
+    /// `callback`.
+    ///
+    /// The future is polled along with the job queue of the runtime, so it makes progress while
+    /// the jobs are run (after every script, or with `JsRuntime::run_jobs` and
+    /// `JsRuntime::block_on`).
+    pub fn new_async<F, Fut, T, E>(mut callback: F) -> Result<Self, JsError>
+    where
+        F: FnMut(JsFunctionContext) -> Fut + 'static,
+        Fut: Future<Output = Result<T, E>> + 'static,
+        T: Into<JsValue>,
+        E: Into<JsValue>,
+    {
+        let callback: Box<dyn FnMut(JsFunctionContext) -> Result<JsPromise, JsError>> =
+            Box::new(move |context: JsFunctionContext| {
+                let runtime = JsRuntimeState::current().ok_or(JsError::NoCurrentContext)?;
+                let (promise, resolver, rejecter) = JsPromise::new()?;
+
+                let arguments = RootedArguments::new(&context.arguments, runtime.clone());
+                let future = callback(context);
+                runtime.spawn(async move {
+                    let _arguments = arguments;
+
+                    // nothing is left to report a failure to settle the promise to
+                    let _ = match future.await {
+                        Ok(value) => resolver.resolve(value),
+                        Err(reason) => rejecter.reject(reason),
+                    };
+                });
+
+                Ok(promise)
+            });
+
+        JsFunction::create(callback, Some(fallible_handler::<JsPromise>))
+    }
+}
+
+/// The arguments of an async function call, kept alive while its future is pending.
+struct RootedArguments {
+    handles: Vec<JsValueRef>,
+    runtime: Rc<JsRuntimeState>,
+}
+
+impl RootedArguments {
+    fn new(arguments: &[JsValue], runtime: Rc<JsRuntimeState>) -> Self {
+        let handles = arguments
+            .iter()
+            .map(|argument| argument.handle)
+            .filter(|&handle| JsError::assert(unsafe { JsAddRef(handle, ptr::null_mut()) }).is_ok())
+            .collect();
+
+        Self { handles, runtime }
+    }
+}
+
+impl Drop for RootedArguments {
+    fn drop(&mut self) {
+        // the arguments are gone with the runtime
+        if !self.runtime.disposed.get() {
+            for &handle in &self.handles {
+                unsafe { JsRelease(handle, ptr::null_mut()) };
+            }
+        }
+    }
+}
+
 impl<T: Into<JsValue>> From<JsFunction<T>> for JsValue {
     fn from(func: JsFunction<T>) -> JsValue {
         JsValue {
@@ -76,6 +181,7 @@ impl<T: Into<JsValue>> From<JsFunction<T>> for JsValue {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::boolean::JsBoolean;
     use crate::context::JsScriptContext;
     use crate::number::JsNumber;
     use crate::object::JsObject;
@@ -83,6 +189,10 @@ mod tests {
     use crate::script::JsScript;
     use crate::string::JsString;
     use crate::value::JsType;
+    use std::future::IntoFuture;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Poll, Waker};
+    use std::thread;
 
     #[test]
     fn create_function() {
@@ -242,4 +352,118 @@ mod tests {
         let script = JsScript::new("test", "log('hello world')").unwrap();
         runtime.run_script(&script).unwrap();
     }
+
+    #[test]
+    fn get_context_data_from_function() {
+        struct Permissions {
+            network: bool,
+        }
+
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_data(Permissions { network: true });
+        context.set_current_context().unwrap();
+
+        let fetch = JsFunction::new(Box::new(|c: JsFunctionContext| {
+            let network = c
+                .data::<Permissions>()
+                .is_some_and(|permissions| permissions.network);
+            JsBoolean::try_from(network).unwrap()
+        }))
+        .unwrap();
+        let key = JsString::new("canFetch").unwrap();
+        let mut global = JsObject::global().unwrap();
+        global.set_property(&key, fetch).unwrap();
+
+        let script = JsScript::new("test", "canFetch()").unwrap();
+        let result = runtime.run_script(&script).unwrap();
+        assert_eq!(JsBoolean::try_from(result).unwrap().try_into(), Ok(true));
+    }
+
+    #[test]
+    fn create_async_function() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let double = JsFunction::new_async(|c: JsFunctionContext| async move {
+            let value: i32 = c
+                .arguments
+                .into_iter()
+                .nth(1)
+                .and_then(|x| JsNumber::try_from(x).ok())
+                .and_then(|x| x.try_into().ok())
+                .ok_or(JsString::new("expected a number").unwrap())?;
+            Ok::<_, JsString>(value * 2)
+        })
+        .unwrap();
+        let mut global = JsObject::global().unwrap();
+        global
+            .set_property(&JsString::new("double").unwrap(), double)
+            .unwrap();
+
+        let script = JsScript::new(
+            "test",
+            "var log = []; \
+             double(21).then(v => log.push(v)); \
+             double('a').catch(e => log.push(e));",
+        )
+        .unwrap();
+        runtime.run_script(&script).unwrap();
+
+        let script = JsScript::new("test", "log.join()").unwrap();
+        let result = JsString::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(result.to_string(), Ok("42,expected a number".to_string()));
+    }
+
+    #[test]
+    fn create_async_function_woken_from_other_thread() {
+        #[derive(Default)]
+        struct Reply {
+            value: Option<i32>,
+            waker: Option<Waker>,
+        }
+
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let request = JsFunction::new_async(|_| {
+            let reply = Arc::new(Mutex::new(Reply::default()));
+            let sender = reply.clone();
+            thread::spawn(move || {
+                let mut reply = sender.lock().unwrap();
+                reply.value = Some(42);
+                if let Some(waker) = reply.waker.take() {
+                    waker.wake();
+                }
+            });
+
+            std::future::poll_fn(move |cx| {
+                let mut reply = reply.lock().unwrap();
+                match reply.value.take() {
+                    Some(value) => Poll::Ready(Ok::<_, JsValue>(value)),
+                    None => {
+                        reply.waker = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .unwrap();
+        let mut global = JsObject::global().unwrap();
+        global
+            .set_property(&JsString::new("request").unwrap(), request)
+            .unwrap();
+
+        let script = JsScript::new("test", "(async () => await request() + 1)()").unwrap();
+        let promise = JsPromise::try_from(runtime.run_script(&script).unwrap()).unwrap();
+
+        let result = runtime
+            .block_on(promise.into_future())
+            .unwrap()
+            .ok()
+            .unwrap();
+        assert_eq!(JsNumber::try_from(result).unwrap().try_into(), Ok(43));
+    }
 }
@@ -0,0 +1,290 @@
+use crate::error::JsError;
+use crate::function::{JsFunction, JsFunctionContext};
+use crate::object::JsObject;
+use crate::promise::{JsPromise, JsPromiseState};
+use crate::runtime::JsRuntimeState;
+use crate::string::JsString;
+use crate::value::JsValue;
+use chakracore_sys::{JsAddRef, JsCreateError, JsRelease, JsValueRef};
+use std::cell::RefCell;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::ptr;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+/// The outcome of a promise, shared with the `then` callbacks.
+#[derive(Default)]
+struct Settlement {
+    /// The value the promise settled with, referenced until it's handed out.
+    result: RefCell<Option<Result<JsValueRef, JsValueRef>>>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl Settlement {
+    fn settle(&self, result: Result<JsValueRef, JsValueRef>) {
+        let (Ok(handle) | Err(handle)) = result;
+        if !handle.is_null() {
+            unsafe { JsAddRef(handle, ptr::null_mut()) };
+        }
+
+        *self.result.borrow_mut() = Some(result);
+        if let Some(waker) = self.waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A future resolving to the value a promise is fulfilled with, or the reason it is rejected
+/// with. Created by awaiting a `JsPromise`.
+///
+/// The future is settled by the runtime's job queue, so it only makes progress while the jobs
+/// are run (e.g. with `JsRuntime::block_on`).
+pub struct JsPromiseFuture {
+    promise: JsValueRef,
+    runtime: Option<Rc<JsRuntimeState>>,
+    settlement: Option<Rc<Settlement>>,
+}
+
+impl JsPromiseFuture {
+    /// Settles the future from the current state of the promise, or registers callbacks to be
+    /// notified once it settles.
+    fn subscribe(&mut self) -> Result<Rc<Settlement>, JsError> {
+        let settlement = Rc::new(Settlement::default());
+        let promise = JsPromise {
+            handle: self.promise,
+        };
+
+        match promise.state()? {
+            JsPromiseState::Fulfilled => settlement.settle(Ok(promise.result()?.handle)),
+            JsPromiseState::Rejected => settlement.settle(Err(promise.result()?.handle)),
+            JsPromiseState::Pending => {
+                let fulfilled = settlement.clone();
+                let on_fulfilled = JsFunction::new(Box::new(move |c: JsFunctionContext| {
+                    fulfilled.settle(Ok(argument(c)));
+                }))?;
+                let rejected = settlement.clone();
+                let on_rejected = JsFunction::new(Box::new(move |c: JsFunctionContext| {
+                    rejected.settle(Err(argument(c)));
+                }))?;
+
+                let this = JsObject::try_from(JsValue {
+                    handle: self.promise,
+                })?;
+                let then = this.get_property(&JsString::new("then")?)?;
+                JsObject::try_from(then)?.call(
+                    &JsValue {
+                        handle: self.promise,
+                    },
+                    &[on_fulfilled.into(), on_rejected.into()],
+                )?;
+            }
+        }
+
+        Ok(settlement)
+    }
+}
+
+/// The first argument passed to a function, or `undefined`.
+fn argument(context: JsFunctionContext) -> JsValueRef {
+    context
+        .arguments
+        .into_iter()
+        .nth(1)
+        .map_or(ptr::null_mut(), |value| value.handle)
+}
+
+/// Reports an engine failure as a rejection.
+fn rejection(error: JsError) -> JsValue {
+    let create = || -> Result<JsValue, JsError> {
+        let message = JsString::new(error.to_string())?;
+        let mut handle = ptr::null_mut();
+        JsError::assert(unsafe { JsCreateError(message.handle, &mut handle) })?;
+        Ok(JsValue { handle })
+    };
+
+    create().unwrap_or_else(|_| ().into())
+}
+
+impl Future for JsPromiseFuture {
+    type Output = Result<JsValue, JsValue>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        let settlement = match &this.settlement {
+            Some(settlement) => settlement.clone(),
+            None => {
+                if this.runtime.is_none() {
+                    return Poll::Ready(Err(rejection(JsError::NoCurrentContext)));
+                }
+
+                match this.subscribe() {
+                    Ok(settlement) => {
+                        this.settlement = Some(settlement.clone());
+                        settlement
+                    }
+                    Err(error) => return Poll::Ready(Err(rejection(error))),
+                }
+            }
+        };
+
+        let result = settlement.result.borrow_mut().take();
+        match result {
+            Some(result) => {
+                let (Ok(handle) | Err(handle)) = result;
+                if !handle.is_null() {
+                    unsafe { JsRelease(handle, ptr::null_mut()) };
+                }
+
+                Poll::Ready(match result {
+                    Ok(handle) => Ok(JsValue { handle }),
+                    Err(handle) => Err(JsValue { handle }),
+                })
+            }
+            None => {
+                *settlement.waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for JsPromiseFuture {
+    fn drop(&mut self) {
+        // the promise is gone with the runtime
+        if let Some(runtime) = &self.runtime {
+            if !runtime.disposed.get() {
+                unsafe { JsRelease(self.promise, ptr::null_mut()) };
+
+                if let Some(settlement) = &self.settlement {
+                    if let Some(Ok(handle) | Err(handle)) = settlement.result.borrow_mut().take() {
+                        if !handle.is_null() {
+                            unsafe { JsRelease(handle, ptr::null_mut()) };
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl IntoFuture for JsPromise {
+    type Output = Result<JsValue, JsValue>;
+    type IntoFuture = JsPromiseFuture;
+
+    fn into_future(self) -> Self::IntoFuture {
+        // the future may be stored on the heap while pending, so the promise is kept alive
+        // explicitly
+        let runtime = JsRuntimeState::current()
+            .filter(|_| JsError::assert(unsafe { JsAddRef(self.handle, ptr::null_mut()) }).is_ok());
+
+        JsPromiseFuture {
+            promise: self.handle,
+            runtime,
+            settlement: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::JsScriptContext;
+    use crate::number::JsNumber;
+    use crate::runtime::JsRuntime;
+    use crate::script::JsScript;
+
+    fn runtime() -> JsRuntime {
+        // keep the promises pending until the future is polled
+        JsRuntime::builder().auto_run_jobs(false).build().unwrap()
+    }
+
+    #[test]
+    fn await_async_function() {
+        let mut runtime = runtime();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new(
+            "test",
+            "(async () => { await null; await null; return 40 + 2; })()",
+        )
+        .unwrap();
+        let promise = JsPromise::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(promise.state(), Ok(JsPromiseState::Pending));
+
+        let result = runtime
+            .block_on(promise.into_future())
+            .unwrap()
+            .ok()
+            .unwrap();
+        assert_eq!(JsNumber::try_from(result).unwrap().try_into(), Ok(42));
+    }
+
+    #[test]
+    fn await_rejected_promise() {
+        let mut runtime = runtime();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script =
+            JsScript::new("test", "(async () => { await null; throw 'failed'; })()").unwrap();
+        let promise = JsPromise::try_from(runtime.run_script(&script).unwrap()).unwrap();
+
+        let reason = runtime
+            .block_on(async { promise.await })
+            .unwrap()
+            .err()
+            .unwrap();
+        assert_eq!(
+            JsString::try_from(reason).unwrap().to_string(),
+            Ok("failed".to_string())
+        );
+    }
+
+    #[test]
+    fn await_thenable() {
+        let mut runtime = runtime();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new("test", "({ then(resolve) { resolve('thenable'); } })").unwrap();
+        let promise = JsPromise::resolve(runtime.run_script(&script).unwrap()).unwrap();
+
+        let result = runtime
+            .block_on(promise.into_future())
+            .unwrap()
+            .ok()
+            .unwrap();
+        assert_eq!(
+            JsString::try_from(result).unwrap().to_string(),
+            Ok("thenable".to_string())
+        );
+    }
+
+    #[test]
+    fn await_promise_resolved_from_rust() {
+        let mut runtime = runtime();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let (promise, resolver, _) = JsPromise::new().unwrap();
+        let mut resolver = Some(resolver);
+        let mut future = promise.into_future();
+
+        let result = runtime
+            .block_on(std::future::poll_fn(|cx| {
+                let poll = Pin::new(&mut future).poll(cx);
+                if let Some(resolver) = resolver.take() {
+                    assert!(poll.is_pending());
+                    resolver.resolve(JsNumber::from(7)).unwrap();
+                }
+                poll
+            }))
+            .unwrap()
+            .ok()
+            .unwrap();
+        assert_eq!(JsNumber::try_from(result).unwrap().try_into(), Ok(7));
+    }
+}
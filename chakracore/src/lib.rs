@@ -1,11 +1,19 @@
 pub mod boolean;
+pub mod cache;
 pub mod context;
 pub mod error;
 pub mod function;
+pub mod future;
+pub mod module;
 pub mod number;
 pub mod object;
+pub mod panic;
+pub mod promise;
 pub mod runtime;
 pub mod script;
+pub mod serialized;
 pub mod string;
+mod task;
+pub mod thread_service;
 pub mod undefined;
 pub mod value;
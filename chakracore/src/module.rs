@@ -0,0 +1,1499 @@
+#![allow(non_upper_case_globals)]
+
+use crate::boolean::JsBoolean;
+use crate::context::{current_context, current_data_or_default, with_context};
+use crate::error::{JsError, JsModuleError, JsSourceError};
+use crate::object::JsObject;
+use crate::panic::catch;
+use crate::runtime::JsRuntimeState;
+use crate::string::JsString;
+use crate::value::JsValue;
+use chakracore_sys::{
+    JsAddRef, JsCreateError, JsErrorCode, JsGetAndClearException, JsGetModuleNamespace,
+    JsGetNullValue, JsInitializeModuleRecord, JsModuleEvaluation, JsModuleHostInfoKind,
+    JsModuleHostInfoKind_JsModuleHostInfo_Exception,
+    JsModuleHostInfoKind_JsModuleHostInfo_FetchImportedModuleCallback,
+    JsModuleHostInfoKind_JsModuleHostInfo_FetchImportedModuleFromScriptCallback,
+    JsModuleHostInfoKind_JsModuleHostInfo_NotifyModuleReadyCallback,
+    JsModuleHostInfoKind_JsModuleHostInfo_Url, JsModuleRecord, JsObjectDefineProperty,
+    JsParseModuleSource, JsParseModuleSourceFlags_JsParseModuleSourceFlags_DataIsUTF8, JsRelease,
+    JsSetModuleHostInfo, JsSetPrototype, JsSourceContext, JsValueRef,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::c_void;
+use std::fmt::{Debug, Formatter, Write};
+use std::fs;
+use std::io;
+use std::os::raw::c_uint;
+use std::path::{Component, Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Resolves and loads the ES modules run with `JsRuntime::run_module` and the modules they
+/// import, see `JsRuntimeBuilder::module_loader`.
+pub trait ModuleLoader: Send + Sync {
+    /// Resolves `specifier` to the specifier identifying a module (e.g. an absolute path or URL).
+    /// `referrer` is the resolved specifier of the importing module, or `None` for the module run
+    /// with `JsRuntime::run_module`.
+    ///
+    /// Each context loads a module once per resolved specifier.
+    fn resolve(&self, specifier: &str, referrer: Option<&str>) -> Result<String, JsModuleError>;
+
+    /// Loads the module identified by a resolved `specifier`.
+    fn load(&self, specifier: &str) -> Result<ModuleSource, JsModuleError>;
+}
+
+/// A module loaded by a `ModuleLoader`.
+#[derive(Clone, Debug)]
+pub enum ModuleSource {
+    /// The JavaScript source of the module.
+    Script(String),
+
+    /// A JSON document, exported as the default export of the module.
+    Json(String),
+
+    /// A module whose exports are created in Rust.
+    Native(NativeModule),
+}
+
+impl From<String> for ModuleSource {
+    fn from(source: String) -> Self {
+        ModuleSource::Script(source)
+    }
+}
+
+impl From<&str> for ModuleSource {
+    fn from(source: &str) -> Self {
+        ModuleSource::Script(source.to_string())
+    }
+}
+
+type ExportsCallback = dyn Fn(&mut JsModuleExports) -> Result<(), JsError> + Send + Sync;
+
+/// A module whose exports are Rust functions and values, created once per context loading the
+/// module.
+#[derive(Clone)]
+pub struct NativeModule(Arc<ExportsCallback>);
+
+impl NativeModule {
+    /// A module exporting the values added by `exports`, which is called in the context loading
+    /// the module.
+    pub fn new<F>(exports: F) -> Self
+    where
+        F: Fn(&mut JsModuleExports) -> Result<(), JsError> + Send + Sync + 'static,
+    {
+        Self(Arc::new(exports))
+    }
+
+    /// Creates the exports in the current context, returning the source of a module exporting
+    /// them.
+    fn instantiate(
+        &self,
+        registry: &ModuleRegistry,
+        record: JsModuleRecord,
+    ) -> Result<String, JsError> {
+        // accessors added to `Object.prototype` by scripts can't intercept the exports
+        let object = JsObject::new()?;
+        let mut null = ptr::null_mut();
+        JsError::assert(unsafe { JsGetNullValue(&mut null) })?;
+        JsError::assert(unsafe { JsSetPrototype(object.handle, null) })?;
+
+        let mut exports = JsModuleExports {
+            object,
+            names: Vec::new(),
+        };
+        (self.0)(&mut exports)?;
+
+        // modules can't be handed values, so the generated module reads the exports from a binding
+        // that only exists while the module is evaluated, see `evaluate_native_modules`
+        registry
+            .native_exports
+            .borrow_mut()
+            .insert(record as usize, NativeExports::new(exports.object)?);
+        let mut source = String::new();
+        for name in &exports.names {
+            if name == "default" {
+                let _ = writeln!(source, "export default {}.default;", NATIVE_EXPORTS);
+            } else {
+                let _ = writeln!(source, "export const {0} = {1}.{0};", name, NATIVE_EXPORTS);
+            }
+        }
+
+        Ok(source)
+    }
+}
+
+/// The global binding a generated native module reads its exports from while it is evaluated.
+const NATIVE_EXPORTS: &str = "__chakracoreNativeExports";
+
+/// The exports of a native module, kept alive until the module is evaluated.
+struct NativeExports {
+    object: JsValueRef,
+    runtime: Rc<JsRuntimeState>,
+}
+
+impl NativeExports {
+    fn new(object: JsObject) -> Result<Self, JsError> {
+        let runtime = JsRuntimeState::current().ok_or(JsError::NoCurrentContext)?;
+        JsError::assert(unsafe { JsAddRef(object.handle, ptr::null_mut()) })?;
+
+        Ok(Self {
+            object: object.handle,
+            runtime,
+        })
+    }
+
+    /// Evaluates the native module, with the exports bound to `NATIVE_EXPORTS` until it's done.
+    fn evaluate(self, record: JsModuleRecord) -> Result<(), JsModuleError> {
+        let global = JsObject::global()?;
+        let key = JsString::new(NATIVE_EXPORTS)?;
+        let mut descriptor = JsObject::new()?;
+        descriptor.set_property(
+            &JsString::new("value")?,
+            JsValue {
+                handle: self.object,
+            },
+        )?;
+        descriptor.set_property(&JsString::new("configurable")?, JsBoolean::try_from(true)?)?;
+        let mut defined = false;
+        let res = unsafe {
+            JsObjectDefineProperty(global.handle, key.handle, descriptor.handle, &mut defined)
+        };
+        JsError::assert(res)?;
+
+        let mut result = ptr::null_mut();
+        let res = unsafe { JsModuleEvaluation(record, &mut result) };
+        let evaluated = match JsError::assert(res) {
+            Err(JsError::ScriptException) => Err(take_exception()),
+            result => Ok(result?),
+        };
+        global.delete_property(&key)?;
+
+        evaluated
+    }
+}
+
+impl Drop for NativeExports {
+    fn drop(&mut self) {
+        // the exports are gone with the runtime
+        if !self.runtime.disposed.get() {
+            unsafe { JsRelease(self.object, ptr::null_mut()) };
+        }
+    }
+}
+
+impl Debug for NativeModule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("NativeModule")
+    }
+}
+
+/// The exports of a `NativeModule`.
+pub struct JsModuleExports {
+    object: JsObject,
+    names: Vec<String>,
+}
+
+impl JsModuleExports {
+    /// Exports `value` as `name`, which must be an identifier that isn't a reserved word, or
+    /// `default`.
+    pub fn export<T: Into<JsValue>>(&mut self, name: &str, value: T) -> Result<(), JsError> {
+        if name != "default" && (!is_identifier(name) || RESERVED_WORDS.contains(&name)) {
+            return Err(JsError::InvalidArgument);
+        }
+
+        self.object.set_property(&JsString::new(name)?, value)?;
+        if !self.names.iter().any(|existing| existing == name) {
+            self.names.push(name.to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// The words that can't name a binding in a module, which is strict mode code.
+const RESERVED_WORDS: &[&str] = &[
+    "arguments",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Quotes a string as a JavaScript string literal.
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c if c.is_control() || c == '\u{2028}' || c == '\u{2029}' => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+/// Serves modules registered in memory, e.g. for tests and bundled preludes, and native modules.
+/// Other modules are loaded by the fallback loader, if any.
+///
+/// Registered modules are identified by the specifier they are registered with. Specifiers
+/// starting with `./` or `../` are resolved relative to the importing module.
+#[derive(Clone, Default)]
+pub struct MemoryModuleLoader {
+    modules: HashMap<String, ModuleSource>,
+    fallback: Option<Arc<dyn ModuleLoader>>,
+}
+
+impl MemoryModuleLoader {
+    /// A loader without any modules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a module with its JavaScript source.
+    pub fn script<S: Into<String>, T: Into<String>>(mut self, specifier: S, source: T) -> Self {
+        self.modules
+            .insert(specifier.into(), ModuleSource::Script(source.into()));
+        self
+    }
+
+    /// Registers a JSON module, exporting the parsed document as its default export.
+    pub fn json<S: Into<String>, T: Into<String>>(mut self, specifier: S, json: T) -> Self {
+        self.modules
+            .insert(specifier.into(), ModuleSource::Json(json.into()));
+        self
+    }
+
+    /// Registers a native module, e.g. `host:config`.
+    pub fn native<S: Into<String>>(mut self, specifier: S, module: NativeModule) -> Self {
+        self.modules
+            .insert(specifier.into(), ModuleSource::Native(module));
+        self
+    }
+
+    /// Sets the loader of the modules that aren't registered.
+    pub fn fallback<L: ModuleLoader + 'static>(mut self, loader: L) -> Self {
+        self.fallback = Some(Arc::new(loader));
+        self
+    }
+}
+
+/// Resolves a `./` or `../` specifier against the specifier of the importing module.
+fn join_relative(referrer: &str, specifier: &str) -> Option<String> {
+    if !specifier.starts_with("./") && !specifier.starts_with("../") {
+        return None;
+    }
+
+    let mut segments: Vec<&str> = referrer.split('/').collect();
+    segments.pop();
+    for segment in specifier.split('/') {
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    Some(segments.join("/"))
+}
+
+impl ModuleLoader for MemoryModuleLoader {
+    fn resolve(&self, specifier: &str, referrer: Option<&str>) -> Result<String, JsModuleError> {
+        let resolved = referrer
+            .and_then(|referrer| join_relative(referrer, specifier))
+            .unwrap_or_else(|| specifier.to_string());
+        if self.modules.contains_key(&resolved) {
+            return Ok(resolved);
+        }
+
+        match &self.fallback {
+            Some(fallback) => fallback.resolve(specifier, referrer),
+            None => Err(JsModuleError::NotFound {
+                specifier: specifier.to_string(),
+            }),
+        }
+    }
+
+    fn load(&self, specifier: &str) -> Result<ModuleSource, JsModuleError> {
+        match (self.modules.get(specifier), &self.fallback) {
+            (Some(module), _) => Ok(module.clone()),
+            (None, Some(fallback)) => fallback.load(specifier),
+            (None, None) => Err(JsModuleError::NotFound {
+                specifier: specifier.to_string(),
+            }),
+        }
+    }
+}
+
+/// Loads modules from the files in a root directory. Modules can't import files outside of the
+/// root, even through symbolic links.
+///
+/// Specifiers starting with `./` or `../` are resolved relative to the importing module, and
+/// absolute paths are used as is. Other specifiers are only accepted for the module run with
+/// `JsRuntime::run_module`, relative to the root. Modules are identified by their canonical path.
+#[derive(Clone, Debug)]
+pub struct FsModuleLoader {
+    root: PathBuf,
+    infer_extensions: bool,
+    json_modules: bool,
+}
+
+impl FsModuleLoader {
+    /// A loader for the modules in the `root` directory.
+    pub fn new<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+            infer_extensions: false,
+            json_modules: false,
+        })
+    }
+
+    /// Sets whether `.json` files are loaded as JSON modules, exporting the parsed document as
+    /// their default export. Disabled by default.
+    pub fn json_modules(mut self, enabled: bool) -> Self {
+        self.json_modules = enabled;
+        self
+    }
+
+    /// Sets whether specifiers may leave out the `.js` or `.mjs` extension, or name a directory
+    /// containing an `index.js`. Disabled by default.
+    pub fn infer_extensions(mut self, enabled: bool) -> Self {
+        self.infer_extensions = enabled;
+        self
+    }
+
+    /// The files a path may refer to, in order of preference.
+    fn candidates(&self, path: PathBuf) -> Vec<PathBuf> {
+        if !self.infer_extensions {
+            return vec![path];
+        }
+
+        let with_extension = |extension: &str| {
+            let mut name = path.clone().into_os_string();
+            name.push(extension);
+            PathBuf::from(name)
+        };
+        let js = with_extension(".js");
+        let mjs = with_extension(".mjs");
+        let index = path.join("index.js");
+
+        vec![path, js, mjs, index]
+    }
+}
+
+/// Resolves `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+impl ModuleLoader for FsModuleLoader {
+    fn resolve(&self, specifier: &str, referrer: Option<&str>) -> Result<String, JsModuleError> {
+        let relative = specifier.starts_with("./") || specifier.starts_with("../");
+        let base = match referrer {
+            Some(referrer) if relative => Path::new(referrer).parent().map(Path::to_path_buf),
+            Some(_) if !Path::new(specifier).is_absolute() => None,
+            _ => Some(self.root.clone()),
+        };
+        let not_found = || JsModuleError::NotFound {
+            specifier: specifier.to_string(),
+        };
+        let outside_root = || JsModuleError::OutsideRoot {
+            specifier: specifier.to_string(),
+        };
+
+        let path = normalize(&base.ok_or_else(not_found)?.join(specifier));
+        if !path.starts_with(&self.root) {
+            return Err(outside_root());
+        }
+
+        let file = self
+            .candidates(path)
+            .into_iter()
+            .find(|candidate| candidate.is_file())
+            .ok_or_else(not_found)?;
+
+        // symbolic links may point out of the root
+        let file = file.canonicalize().map_err(|_| not_found())?;
+        if !file.starts_with(&self.root) {
+            return Err(outside_root());
+        }
+
+        file.into_os_string().into_string().map_err(|_| not_found())
+    }
+
+    fn load(&self, specifier: &str) -> Result<ModuleSource, JsModuleError> {
+        let source = |source: JsSourceError| JsModuleError::Source {
+            specifier: specifier.to_string(),
+            source,
+        };
+
+        let bytes = fs::read(specifier).map_err(|error| source(error.into()))?;
+        let mut text = String::from_utf8(bytes).map_err(|error| {
+            source(JsSourceError::InvalidUtf8 {
+                offset: error.utf8_error().valid_up_to(),
+            })
+        })?;
+
+        // a byte order mark isn't valid JavaScript
+        if text.starts_with('\u{feff}') {
+            text.drain(..'\u{feff}'.len_utf8());
+        }
+
+        if self.json_modules
+            && Path::new(specifier)
+                .extension()
+                .is_some_and(|e| e == "json")
+        {
+            Ok(ModuleSource::Json(text))
+        } else {
+            Ok(ModuleSource::Script(text))
+        }
+    }
+}
+
+/// An ES module run with `JsRuntime::run_module`.
+#[derive(Debug)]
+pub struct JsModule {
+    record: JsModuleRecord,
+    specifier: String,
+}
+
+impl JsModule {
+    /// The resolved specifier of the module.
+    pub fn specifier(&self) -> &str {
+        &self.specifier
+    }
+
+    /// The namespace object of the module, holding its exports.
+    pub fn namespace(&self) -> Result<JsObject, JsError> {
+        let mut handle = ptr::null_mut();
+        let res = unsafe { JsGetModuleNamespace(self.record, &mut handle) };
+        JsError::assert(res)?;
+
+        JsObject::try_from(JsValue { handle })
+    }
+}
+
+/// A module waiting for its source to be parsed.
+struct PendingModule {
+    record: JsModuleRecord,
+    specifier: String,
+    /// Set if the module could not be resolved.
+    error: Option<JsModuleError>,
+}
+
+/// The modules of a context.
+#[derive(Default)]
+struct ModuleRegistry {
+    records: RefCell<HashMap<String, JsModuleRecord>>,
+    /// The records failing the imports that couldn't be resolved, by the resolved specifier of the
+    /// importing module and the specifier.
+    unresolved: RefCell<HashMap<(Option<String>, String), JsModuleRecord>>,
+    /// The resolved specifiers of the records, to resolve their imports.
+    specifiers: RefCell<HashMap<usize, String>>,
+    pending: RefCell<VecDeque<PendingModule>>,
+    /// Set while the job loading dynamic imports is queued.
+    scheduled: Cell<bool>,
+    /// The modules whose imports are all parsed, with the exception if any failed.
+    ready: RefCell<HashMap<usize, Result<(), String>>>,
+    /// The modules imported by each module, to find the native modules a module imports.
+    imports: RefCell<HashMap<usize, Vec<JsModuleRecord>>>,
+    /// The exports of the native modules that weren't evaluated yet.
+    native_exports: RefCell<HashMap<usize, NativeExports>>,
+}
+
+impl ModuleRegistry {
+    fn current() -> Result<Rc<Self>, JsError> {
+        current_data_or_default().ok_or(JsError::NoCurrentContext)
+    }
+
+    /// The record of the module identified by `specifier`, created and queued for parsing if the
+    /// module wasn't requested before.
+    fn record(&self, referrer: JsModuleRecord, specifier: &str) -> Result<JsModuleRecord, JsError> {
+        if let Some(&record) = self.records.borrow().get(specifier) {
+            return Ok(record);
+        }
+
+        let record = self.create(referrer, specifier)?;
+        self.records
+            .borrow_mut()
+            .insert(specifier.to_string(), record);
+        self.queue(record, specifier, None);
+        Ok(record)
+    }
+
+    fn create(&self, referrer: JsModuleRecord, specifier: &str) -> Result<JsModuleRecord, JsError> {
+        let name = JsString::new(specifier)?;
+        let mut record = ptr::null_mut();
+        let res = unsafe { JsInitializeModuleRecord(referrer, name.handle, &mut record) };
+        JsError::assert(res)?;
+
+        // the url shows up in stack traces
+        set_host_info(
+            record,
+            JsModuleHostInfoKind_JsModuleHostInfo_Url,
+            name.handle,
+        )?;
+
+        self.specifiers
+            .borrow_mut()
+            .insert(record as usize, specifier.to_string());
+        Ok(record)
+    }
+
+    /// The record failing an import that couldn't be resolved, created and queued for parsing if
+    /// the import wasn't requested before.
+    fn unresolved(
+        &self,
+        referrer: JsModuleRecord,
+        referrer_specifier: Option<String>,
+        specifier: &str,
+        error: JsModuleError,
+    ) -> Result<JsModuleRecord, JsError> {
+        let key = (referrer_specifier, specifier.to_string());
+        if let Some(&record) = self.unresolved.borrow().get(&key) {
+            return Ok(record);
+        }
+
+        let record = self.create(referrer, specifier)?;
+        self.unresolved.borrow_mut().insert(key, record);
+        self.queue(record, specifier, Some(error));
+        Ok(record)
+    }
+
+    /// Forgets every record that isn't in `known`, e.g. the records of a graph that failed to
+    /// link.
+    fn forget_except(&self, known: &HashSet<usize>) {
+        let known = |record: &JsModuleRecord| known.contains(&(*record as usize));
+        self.records.borrow_mut().retain(|_, record| known(record));
+        self.unresolved
+            .borrow_mut()
+            .retain(|_, record| known(record));
+        self.specifiers
+            .borrow_mut()
+            .retain(|record, _| known(&(*record as JsModuleRecord)));
+        self.pending
+            .borrow_mut()
+            .retain(|pending| known(&pending.record));
+        self.ready
+            .borrow_mut()
+            .retain(|record, _| known(&(*record as JsModuleRecord)));
+        self.native_exports
+            .borrow_mut()
+            .retain(|record, _| known(&(*record as JsModuleRecord)));
+
+        let mut imports = self.imports.borrow_mut();
+        imports.retain(|record, _| known(&(*record as JsModuleRecord)));
+        for records in imports.values_mut() {
+            records.retain(known);
+        }
+    }
+
+    fn queue(&self, record: JsModuleRecord, specifier: &str, error: Option<JsModuleError>) {
+        self.pending.borrow_mut().push_back(PendingModule {
+            record,
+            specifier: specifier.to_string(),
+            error,
+        });
+    }
+}
+
+fn set_host_info(
+    record: JsModuleRecord,
+    kind: JsModuleHostInfoKind,
+    info: *mut c_void,
+) -> Result<(), JsError> {
+    JsError::assert(unsafe { JsSetModuleHostInfo(record, kind, info) })
+}
+
+/// Converts an exception to a string for `JsModuleError::Exception`.
+fn exception_message(exception: JsValueRef) -> String {
+    JsString::try_from(JsValue { handle: exception })
+        .and_then(|message| message.to_string())
+        .unwrap_or_else(|error| error.to_string())
+}
+
+/// Takes the exception thrown by a failed call into the engine.
+fn take_exception() -> JsModuleError {
+    let mut exception = ptr::null_mut();
+    match JsError::assert(unsafe { JsGetAndClearException(&mut exception) }) {
+        Ok(()) => JsModuleError::Exception(exception_message(exception)),
+        Err(error) => error.into(),
+    }
+}
+
+/// Fails the module with `error`, which the engine reports to the importing modules.
+fn fail(record: JsModuleRecord, error: &JsModuleError) -> Result<(), JsError> {
+    let message = JsString::new(error.to_string())?;
+    let mut exception = ptr::null_mut();
+    JsError::assert(unsafe { JsCreateError(message.handle, &mut exception) })?;
+
+    set_host_info(
+        record,
+        JsModuleHostInfoKind_JsModuleHostInfo_Exception,
+        exception,
+    )
+}
+
+fn parse(
+    registry: &ModuleRegistry,
+    record: JsModuleRecord,
+    source: ModuleSource,
+) -> Result<(), JsModuleError> {
+    let source = match source {
+        ModuleSource::Script(source) => source,
+        // invalid documents fail when the module is evaluated
+        ModuleSource::Json(json) => format!("export default JSON.parse({});", quote(&json)),
+        ModuleSource::Native(module) => module.instantiate(registry, record)?,
+    };
+
+    let length = c_uint::try_from(source.len()).map_err(|_| JsError::InvalidArgument)?;
+    let mut exception = ptr::null_mut();
+    let res = unsafe {
+        JsParseModuleSource(
+            record,
+            0,
+            source.as_ptr() as *mut _,
+            length,
+            JsParseModuleSourceFlags_JsParseModuleSourceFlags_DataIsUTF8,
+            &mut exception,
+        )
+    };
+
+    match JsError::assert(res) {
+        Err(JsError::ScriptCompile) if !exception.is_null() => {
+            Err(JsModuleError::Exception(exception_message(exception)))
+        }
+        result => Ok(result?),
+    }
+}
+
+/// The record of a module imported by `referencing_module`, or by a script if null.
+fn fetch(
+    state: Option<&Rc<JsRuntimeState>>,
+    referencing_module: JsModuleRecord,
+    specifier: JsValueRef,
+) -> Result<JsModuleRecord, JsError> {
+    let state = state.ok_or(JsError::InvalidContext)?;
+    let loader = state.module_loader.clone().ok_or(JsError::InvalidContext)?;
+    let registry = ModuleRegistry::current()?;
+    let specifier = JsString::try_from(JsValue { handle: specifier })?.to_string()?;
+    let referrer = registry
+        .specifiers
+        .borrow()
+        .get(&(referencing_module as usize))
+        .cloned();
+
+    let record = match loader.resolve(&specifier, referrer.as_deref()) {
+        Ok(resolved) => registry.record(referencing_module, &resolved)?,
+        // failed when the module is parsed, so the error reaches the importing module
+        Err(error) => registry.unresolved(referencing_module, referrer, &specifier, error)?,
+    };
+
+    if !referencing_module.is_null() {
+        registry
+            .imports
+            .borrow_mut()
+            .entry(referencing_module as usize)
+            .or_default()
+            .push(record);
+    }
+
+    // dynamic imports are loaded by the job queue
+    if !registry.scheduled.replace(true) {
+        let context = current_context()?;
+        let registry = Rc::downgrade(&registry);
+        state.spawn(async move {
+            if let Some(registry) = registry.upgrade() {
+                // the registry is dropped with the context, so the context is still alive
+                let _ = with_context(context, || {
+                    registry.scheduled.set(false);
+                    load_dynamic_imports(&*loader, &registry)
+                });
+            }
+        });
+    }
+
+    Ok(record)
+}
+
+unsafe fn fetch_callback(
+    referencing_module: JsModuleRecord,
+    specifier: JsValueRef,
+    dependent_module_record: *mut JsModuleRecord,
+) -> JsErrorCode {
+    let state = JsRuntimeState::current();
+    let failed = JsError::InvalidArgument.raw_code();
+
+    catch(state.as_deref(), failed, || {
+        match fetch(state.as_ref(), referencing_module, specifier) {
+            Ok(record) => {
+                *dependent_module_record = record;
+                0
+            }
+            Err(error) => error.raw_code(),
+        }
+    })
+}
+
+unsafe extern "C" fn fetch_imported_module(
+    referencing_module: JsModuleRecord,
+    specifier: JsValueRef,
+    dependent_module_record: *mut JsModuleRecord,
+) -> JsErrorCode {
+    fetch_callback(referencing_module, specifier, dependent_module_record)
+}
+
+/// Called for `import()` in scripts. The modules are resolved without a referrer.
+unsafe extern "C" fn fetch_imported_module_from_script(
+    _referencing_source_context: JsSourceContext,
+    specifier: JsValueRef,
+    dependent_module_record: *mut JsModuleRecord,
+) -> JsErrorCode {
+    fetch_callback(ptr::null_mut(), specifier, dependent_module_record)
+}
+
+unsafe extern "C" fn notify_module_ready(
+    referencing_module: JsModuleRecord,
+    exception: JsValueRef,
+) -> JsErrorCode {
+    let state = JsRuntimeState::current();
+
+    // the return value is ignored
+    catch(state.as_deref(), 0, || {
+        if let Ok(registry) = ModuleRegistry::current() {
+            let result = if exception.is_null() {
+                Ok(())
+            } else {
+                Err(exception_message(exception))
+            };
+            registry
+                .ready
+                .borrow_mut()
+                .insert(referencing_module as usize, result);
+        }
+
+        0
+    })
+}
+
+/// Registers the module callbacks on the current context, which are shared by all modules of the
+/// context.
+pub(crate) fn attach() -> Result<(), JsError> {
+    let name = JsString::new("")?;
+    let mut record = ptr::null_mut();
+    let res = unsafe { JsInitializeModuleRecord(ptr::null_mut(), name.handle, &mut record) };
+    JsError::assert(res)?;
+
+    set_host_info(
+        record,
+        JsModuleHostInfoKind_JsModuleHostInfo_FetchImportedModuleCallback,
+        fetch_imported_module as *mut c_void,
+    )?;
+    set_host_info(
+        record,
+        JsModuleHostInfoKind_JsModuleHostInfo_FetchImportedModuleFromScriptCallback,
+        fetch_imported_module_from_script as *mut c_void,
+    )?;
+    set_host_info(
+        record,
+        JsModuleHostInfoKind_JsModuleHostInfo_NotifyModuleReadyCallback,
+        notify_module_ready as *mut c_void,
+    )
+}
+
+/// Loads the module identified by `specifier` and its imports in the current context, and
+/// evaluates it.
+pub(crate) fn run(state: &JsRuntimeState, specifier: &str) -> Result<JsModule, JsModuleError> {
+    let loader = state.module_loader.clone().ok_or(JsModuleError::NoLoader)?;
+    let registry = ModuleRegistry::current()?;
+    let specifier = loader.resolve(specifier, None)?;
+
+    let existing = registry.records.borrow().get(&specifier).copied();
+    let record = match existing {
+        Some(record) => record,
+        None => {
+            // loaded up front, so a missing root module is reported as is
+            let source = loader.load(&specifier)?;
+            let known: HashSet<usize> = registry.specifiers.borrow().keys().copied().collect();
+            let record = registry.create(ptr::null_mut(), &specifier)?;
+            registry
+                .records
+                .borrow_mut()
+                .insert(specifier.clone(), record);
+
+            let link = || -> Result<(), JsModuleError> {
+                parse(&registry, record, source)?;
+                load_imports(&*loader, &registry)?;
+
+                let ready = registry.ready.borrow_mut().remove(&(record as usize));
+                match ready {
+                    Some(Ok(())) => Ok(()),
+                    Some(Err(message)) => Err(JsModuleError::Exception(message)),
+                    None => Err(JsError::ModuleNotEvaluated.into()),
+                }
+            };
+
+            // records that failed to parse or link can't be evaluated, so the modules of the graph
+            // are loaded again the next time the module is run
+            if let Err(error) = link() {
+                registry.forget_except(&known);
+                return Err(error);
+            }
+
+            record
+        }
+    };
+
+    evaluate_native_modules(&registry, record)?;
+    let mut result = ptr::null_mut();
+    let res = unsafe { JsModuleEvaluation(record, &mut result) };
+    match JsError::assert(res) {
+        Err(JsError::ScriptException) => return Err(take_exception()),
+        result => result?,
+    }
+
+    Ok(JsModule { record, specifier })
+}
+
+/// Evaluates the native modules imported by `root`, or `root` itself, that weren't evaluated yet.
+///
+/// Each one is evaluated on its own before `root`, with its exports bound to `NATIVE_EXPORTS`
+/// until it's done. Only the generated source runs meanwhile, so scripts and other modules can't
+/// reach the exports. Native modules don't import anything, so evaluating them early can't be
+/// observed.
+fn evaluate_native_modules(
+    registry: &ModuleRegistry,
+    root: JsModuleRecord,
+) -> Result<(), JsModuleError> {
+    let mut visited = HashSet::new();
+    let mut records = vec![root];
+    while let Some(record) = records.pop() {
+        if !visited.insert(record as usize) {
+            continue;
+        }
+        if let Some(imports) = registry.imports.borrow().get(&(record as usize)) {
+            records.extend(imports);
+        }
+
+        let exports = registry
+            .native_exports
+            .borrow_mut()
+            .remove(&(record as usize));
+        if let Some(exports) = exports {
+            exports.evaluate(record)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the modules imported while parsing, until all imports are parsed. Modules failing to
+/// load or parse are reported to the modules importing them.
+fn load_imports(loader: &dyn ModuleLoader, registry: &ModuleRegistry) -> Result<(), JsError> {
+    loop {
+        let pending = registry.pending.borrow_mut().pop_front();
+        let pending = match pending {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        let loaded = match pending.error {
+            Some(error) => Err(error),
+            None => loader
+                .load(&pending.specifier)
+                .and_then(|source| parse(registry, pending.record, source)),
+        };
+
+        match loaded {
+            // the engine already reports syntax errors
+            Ok(()) | Err(JsModuleError::Exception(_)) => {}
+            Err(error) => fail(pending.record, &error)?,
+        }
+    }
+}
+
+/// Loads the modules imported with `import()` and evaluates the ones ready to run, which settles
+/// the promises returned by `import()`. Failed modules are rejected by the engine.
+fn load_dynamic_imports(
+    loader: &dyn ModuleLoader,
+    registry: &ModuleRegistry,
+) -> Result<(), JsError> {
+    load_imports(loader, registry)?;
+
+    let ready: Vec<_> = registry.ready.borrow_mut().drain().collect();
+    for (record, result) in ready {
+        if result.is_ok() {
+            let record = record as JsModuleRecord;
+            // a native module that failed leaves its bindings uninitialized, which rejects the
+            // promise once the module importing it is evaluated
+            let _ = evaluate_native_modules(registry, record);
+            let mut value = ptr::null_mut();
+            let res = unsafe { JsModuleEvaluation(record, &mut value) };
+            if JsError::assert(res).is_err() {
+                // the exception rejects the promise
+                unsafe { JsGetAndClearException(&mut value) };
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::JsScriptContext;
+    use crate::function::{JsFunction, JsFunctionContext};
+    use crate::number::JsNumber;
+    use crate::promise::JsPromise;
+    use crate::runtime::JsRuntime;
+    use crate::script::JsScript;
+    use std::collections::HashMap;
+    use std::future::IntoFuture;
+
+    /// Serves modules from a map, resolving specifiers relative to the importing module.
+    struct MapLoader(HashMap<&'static str, &'static str>);
+
+    impl ModuleLoader for MapLoader {
+        fn resolve(
+            &self,
+            specifier: &str,
+            referrer: Option<&str>,
+        ) -> Result<String, JsModuleError> {
+            let resolved = match (specifier.strip_prefix("./"), referrer) {
+                (Some(name), Some(referrer)) => match referrer.rsplit_once('/') {
+                    Some((directory, _)) => format!("{}/{}", directory, name),
+                    None => name.to_string(),
+                },
+                _ => specifier.to_string(),
+            };
+
+            if self.0.contains_key(resolved.as_str()) {
+                Ok(resolved)
+            } else {
+                Err(JsModuleError::NotFound {
+                    specifier: specifier.to_string(),
+                })
+            }
+        }
+
+        fn load(&self, specifier: &str) -> Result<ModuleSource, JsModuleError> {
+            Ok(self.0[specifier].into())
+        }
+    }
+
+    fn runtime(modules: &[(&'static str, &'static str)]) -> JsRuntime {
+        JsRuntime::builder()
+            .module_loader(MapLoader(modules.iter().copied().collect()))
+            .build()
+            .unwrap()
+    }
+
+    fn export(module: &JsModule, name: &str) -> JsValue {
+        let namespace = module.namespace().unwrap();
+        namespace
+            .get_property(&JsString::new(name).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn run_module_with_imports() {
+        let mut runtime = runtime(&[
+            (
+                "app/main.js",
+                "import { add } from './math.js'; \
+                 import { twice } from './twice.js'; \
+                 export const answer = twice(add(20, 1));",
+            ),
+            ("app/math.js", "export function add(a, b) { return a + b; }"),
+            (
+                "app/twice.js",
+                "import { add } from './math.js'; \
+                 export const twice = x => add(x, x);",
+            ),
+        ]);
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let module = runtime.run_module("app/main.js").unwrap();
+        assert_eq!(module.specifier(), "app/main.js");
+
+        let answer = JsNumber::try_from(export(&module, "answer")).unwrap();
+        assert_eq!(answer.try_into(), Ok(42));
+    }
+
+    #[test]
+    fn run_module_once_per_context() {
+        let mut runtime = runtime(&[(
+            "counter.js",
+            "globalThis.runs = (globalThis.runs || 0) + 1; export const runs = globalThis.runs;",
+        )]);
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        runtime.run_module("counter.js").unwrap();
+        let module = runtime.run_module("counter.js").unwrap();
+
+        let runs = JsNumber::try_from(export(&module, "runs")).unwrap();
+        assert_eq!(runs.try_into(), Ok(1));
+    }
+
+    #[test]
+    fn report_module_errors() {
+        let mut runtime = runtime(&[
+            ("missing.js", "import './nowhere.js';"),
+            ("syntax.js", "import './broken.js';"),
+            ("broken.js", "export const = 1;"),
+            ("throws.js", "throw new Error('failed');"),
+        ]);
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        assert!(matches!(
+            runtime.run_module("unknown.js"),
+            Err(JsModuleError::NotFound { specifier }) if specifier == "unknown.js"
+        ));
+
+        match runtime.run_module("missing.js") {
+            Err(JsModuleError::Exception(message)) => {
+                assert!(message.contains("Cannot find module './nowhere.js'."))
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        assert!(matches!(
+            runtime.run_module("syntax.js"),
+            Err(JsModuleError::Exception(message)) if message.starts_with("SyntaxError")
+        ));
+
+        assert!(matches!(
+            runtime.run_module("throws.js"),
+            Err(JsModuleError::Exception(message)) if message == "Error: failed"
+        ));
+    }
+
+    #[test]
+    fn run_module_without_loader() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        assert!(matches!(
+            runtime.run_module("main.js"),
+            Err(JsModuleError::NoLoader)
+        ));
+    }
+
+    #[test]
+    fn run_module_again_after_failure() {
+        let directory =
+            std::env::temp_dir().join(format!("chakracore-retry-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("main.js"),
+            "import { value } from './dep.js'; export const result = value;",
+        )
+        .unwrap();
+        fs::write(directory.join("dep.js"), "export const value = (;").unwrap();
+
+        let loader = FsModuleLoader::new(&directory).unwrap();
+        let mut runtime = JsRuntime::builder().module_loader(loader).build().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        // the error is reported again rather than evaluating the unlinked module, and the records
+        // of the graph are forgotten
+        for _ in 0..2 {
+            assert!(matches!(
+                runtime.run_module("main.js"),
+                Err(JsModuleError::Exception(message)) if message.starts_with("SyntaxError")
+            ));
+
+            let registry = ModuleRegistry::current().unwrap();
+            assert!(registry.records.borrow().is_empty());
+            assert!(registry.specifiers.borrow().is_empty());
+            assert!(registry.imports.borrow().is_empty());
+        }
+
+        fs::write(directory.join("dep.js"), "export const value = 42;").unwrap();
+        let module = runtime.run_module("main.js").unwrap();
+        let result = JsNumber::try_from(export(&module, "result")).unwrap();
+        assert_eq!(result.try_into(), Ok(42));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn load_modules_from_files() {
+        let directory =
+            std::env::temp_dir().join(format!("chakracore-module-{}", std::process::id()));
+        let root = directory.join("root");
+        fs::create_dir_all(root.join("app/lib")).unwrap();
+
+        let files = [
+            (
+                "root/app/main.js",
+                "import { value } from './lib'; \
+                 import { other } from './lib/other.mjs'; \
+                 export const result = value + other;",
+            ),
+            (
+                "root/app/lib/index.js",
+                "import './counter'; export const value = 40;",
+            ),
+            (
+                "root/app/lib/other.mjs",
+                "import '../lib/counter.js'; export const other = 2;",
+            ),
+            (
+                "root/app/lib/counter.js",
+                "globalThis.loads = (globalThis.loads || 0) + 1;",
+            ),
+            ("root/app/escape.js", "import '../../outside.js';"),
+            ("outside.js", "export const secret = 1;"),
+        ];
+        for (path, source) in files {
+            fs::write(directory.join(path), source).unwrap();
+        }
+
+        let loader = FsModuleLoader::new(&root).unwrap();
+        let main = loader.resolve("app/main.js", None).unwrap();
+        assert!(matches!(
+            loader.resolve("./lib", Some(&main)),
+            Err(JsModuleError::NotFound { .. })
+        ));
+        assert!(matches!(
+            loader.resolve("../outside.js", None),
+            Err(JsModuleError::OutsideRoot { .. })
+        ));
+
+        let mut runtime = JsRuntime::builder()
+            .module_loader(loader.infer_extensions(true))
+            .build()
+            .unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let module = runtime.run_module("app/main.js").unwrap();
+        assert_eq!(module.specifier(), main);
+        let result = JsNumber::try_from(export(&module, "result")).unwrap();
+        assert_eq!(result.try_into(), Ok(42));
+
+        // imported through different paths, but evaluated once
+        let script = JsScript::new("test", "loads").unwrap();
+        let loads = JsNumber::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(loads.try_into(), Ok(1));
+
+        assert!(matches!(
+            runtime.run_module("app/escape.js"),
+            Err(JsModuleError::Exception(message)) if message.contains("outside of the allowed root")
+        ));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn load_memory_and_native_modules() {
+        let config = NativeModule::new(|exports| {
+            let read_config = JsFunction::new(Box::new(|c: JsFunctionContext| {
+                let key = c.arguments.into_iter().nth(1).unwrap();
+                let key = JsString::try_from(key).unwrap().to_string().unwrap();
+                JsString::new(format!("value of {}", key)).unwrap()
+            }))?;
+            exports.export("readConfig", read_config)?;
+            exports.export("default", JsNumber::from(2))
+        });
+        let loader = MemoryModuleLoader::new()
+            .native("host:config", config)
+            .script("prelude/index.js", "export { greet } from './greet.js';")
+            .script(
+                "prelude/greet.js",
+                "export const greet = name => `hello ${name}`;",
+            )
+            .script(
+                "main.js",
+                "import version, { readConfig } from 'host:config'; \
+                 import { greet } from 'prelude/index.js'; \
+                 export const result = `${greet('world')}, ${readConfig('port')}, v${version}`;",
+            );
+
+        let mut runtime = JsRuntime::builder().module_loader(loader).build().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let module = runtime.run_module("main.js").unwrap();
+        let result = JsString::try_from(export(&module, "result")).unwrap();
+        assert_eq!(
+            result.to_string(),
+            Ok("hello world, value of port, v2".to_string())
+        );
+
+        // the native module is loaded once, and its exports aren't left on the global object
+        let config = runtime.run_module("host:config").unwrap();
+        let version = JsNumber::try_from(export(&config, "default")).unwrap();
+        assert_eq!(version.try_into(), Ok(2));
+
+        let script = JsScript::new("test", "Object.keys(globalThis).join()").unwrap();
+        let globals = JsString::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert!(!globals.to_string().unwrap().contains("chakracore"));
+    }
+
+    #[test]
+    fn protect_native_exports_from_other_modules() {
+        let config = NativeModule::new(|exports| exports.export("port", JsNumber::from(8080)));
+        let secret = NativeModule::new(|exports| exports.export("token", JsNumber::from(42)));
+        let loader = MemoryModuleLoader::new()
+            .native("host:config", config)
+            .native("host:secret", secret)
+            .script(
+                "evil.js",
+                "Object.defineProperty(Object.prototype, 'port', { get() { return 2; } }); \
+                 export const seen = typeof __chakracoreNativeExports;",
+            )
+            .script(
+                "main.js",
+                "import { seen } from 'evil.js'; \
+                 import { port } from 'host:config'; \
+                 export const result = `${seen}, ${port}, ${typeof __chakracoreNativeExports}`;",
+            );
+
+        let mut runtime = JsRuntime::builder().module_loader(loader).build().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let module = runtime.run_module("main.js").unwrap();
+        let result = JsString::try_from(export(&module, "result")).unwrap();
+        assert_eq!(
+            result.to_string(),
+            Ok("undefined, 8080, undefined".to_string())
+        );
+
+        // native modules imported dynamically are evaluated the same way
+        let script = JsScript::new(
+            "test",
+            "var result; import('host:secret').then(m => result = m.token);",
+        )
+        .unwrap();
+        runtime.run_script(&script).unwrap();
+
+        let script = JsScript::new("test", "result").unwrap();
+        let result = JsNumber::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(result.try_into(), Ok(42));
+    }
+
+    #[test]
+    fn reject_invalid_export_names() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let mut exports = JsModuleExports {
+            object: JsObject::new().unwrap(),
+            names: Vec::new(),
+        };
+        assert_eq!(
+            exports.export("read-config", 1),
+            Err(JsError::InvalidArgument)
+        );
+        assert_eq!(exports.export("", 1), Err(JsError::InvalidArgument));
+        assert_eq!(exports.export("new", 1), Err(JsError::InvalidArgument));
+        assert_eq!(exports.export("class", 1), Err(JsError::InvalidArgument));
+        assert_eq!(exports.export("$read_config2", 1), Ok(()));
+        assert_eq!(exports.export("default", 1), Ok(()));
+    }
+
+    #[test]
+    fn import_dynamically_from_script() {
+        let mut runtime = runtime(&[
+            (
+                "plugin/lib.js",
+                "import { base } from './base.js'; export const value = base + 2;",
+            ),
+            ("plugin/base.js", "export const base = 40;"),
+        ]);
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new(
+            "test",
+            "var result; import('plugin/lib.js').then(m => result = m.value);",
+        )
+        .unwrap();
+        runtime.run_script(&script).unwrap();
+
+        // settled by the job queue after the script
+        let script = JsScript::new("test", "result").unwrap();
+        let result = JsNumber::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(result.try_into(), Ok(42));
+
+        let script = JsScript::new(
+            "test",
+            "var error; import('missing.js').catch(e => error = e.message);",
+        )
+        .unwrap();
+        runtime.run_script(&script).unwrap();
+
+        let script = JsScript::new("test", "error").unwrap();
+        let error = JsString::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(
+            error.to_string(),
+            Ok("Cannot find module 'missing.js'.".to_string())
+        );
+
+        // the failed record is reused by later imports
+        let registry = ModuleRegistry::current().unwrap();
+        let records = registry.specifiers.borrow().len();
+        let script = JsScript::new(
+            "test",
+            "error = undefined; import('missing.js').catch(e => error = e.message);",
+        )
+        .unwrap();
+        runtime.run_script(&script).unwrap();
+
+        let script = JsScript::new("test", "error").unwrap();
+        let error = JsString::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(
+            error.to_string(),
+            Ok("Cannot find module 'missing.js'.".to_string())
+        );
+        assert_eq!(registry.specifiers.borrow().len(), records);
+    }
+
+    #[test]
+    fn import_dynamically_from_module() {
+        let mut runtime = runtime(&[
+            (
+                "app/main.js",
+                "export const lazy = import('./lazy.js').then(m => m.answer);",
+            ),
+            ("app/lazy.js", "export const answer = 42;"),
+        ]);
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let module = runtime.run_module("app/main.js").unwrap();
+        let lazy = JsPromise::try_from(export(&module, "lazy")).unwrap();
+
+        let answer = runtime.block_on(lazy.into_future()).unwrap().ok().unwrap();
+        assert_eq!(JsNumber::try_from(answer).unwrap().try_into(), Ok(42));
+    }
+
+    #[test]
+    fn load_json_modules() {
+        let directory =
+            std::env::temp_dir().join(format!("chakracore-json-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("main.js"),
+            "import config from './config.json'; \
+             import defaults from 'defaults.json'; \
+             export const result = `${config.name}:${config.port || defaults.port}`;",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("config.json"),
+            "{ \"name\": \"api \\\"v2\\\"\\u2028\" }",
+        )
+        .unwrap();
+
+        let loader = MemoryModuleLoader::new()
+            .json("defaults.json", r#"{ "port": 8080 }"#)
+            .fallback(FsModuleLoader::new(&directory).unwrap().json_modules(true));
+        let mut runtime = JsRuntime::builder().module_loader(loader).build().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let module = runtime.run_module("main.js").unwrap();
+        let result = JsString::try_from(export(&module, "result")).unwrap();
+        assert_eq!(
+            result.to_string(),
+            Ok("api \"v2\"\u{2028}:8080".to_string())
+        );
+
+        // without the option, JSON files are parsed as scripts
+        let loader = FsModuleLoader::new(&directory).unwrap();
+        let mut runtime = JsRuntime::builder().module_loader(loader).build().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+        assert!(matches!(
+            runtime.run_module("config.json"),
+            Err(JsModuleError::Exception(message)) if message.starts_with("SyntaxError")
+        ));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::JsError;
+use crate::runtime::JsRuntimeState;
 use crate::value::JsValue;
 use chakracore_sys::{
     JsConvertValueToNumber, JsDoubleToNumber, JsIntToNumber, JsNumberToDouble, JsNumberToInt,
@@ -36,7 +37,9 @@ impl TryFrom<JsValue> for JsNumber {
 
     fn try_from(value: JsValue) -> Result<Self, Self::Error> {
         let mut result = ptr::null_mut();
+        // `valueOf` and `toString` may call back into Rust
         let res = unsafe { JsConvertValueToNumber(value.handle, &mut result) };
+        JsRuntimeState::resume_current_panic();
         JsError::assert(res)?;
 
         Ok(JsNumber { handle: result })
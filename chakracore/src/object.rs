@@ -1,16 +1,20 @@
 use crate::boolean::JsBoolean;
+use crate::context::with_owning_context;
 use crate::error::JsError;
+use crate::runtime::JsRuntimeState;
 use crate::string::JsString;
 use crate::value::JsValue;
 use chakracore_sys::{
-    JsCreateObject, JsGetGlobalObject, JsObjectDeleteProperty, JsObjectGetProperty,
-    JsObjectHasProperty, JsObjectSetProperty, JsValueRef,
+    JsCallFunction, JsConvertValueToObject, JsCreateObject, JsGetGlobalObject,
+    JsObjectDeleteProperty, JsObjectGetProperty, JsObjectHasProperty, JsObjectSetProperty,
+    JsValueRef,
 };
+use std::os::raw::c_ushort;
 use std::ptr;
 
 #[derive(Debug)]
 pub struct JsObject {
-    handle: JsValueRef,
+    pub(crate) handle: JsValueRef,
 }
 
 impl JsObject {
@@ -30,12 +34,20 @@ impl JsObject {
         Ok(Self { handle: result })
     }
 
+    // Objects from other contexts of the runtime are accessed in the context owning them. Accessors,
+    // proxies and functions may call back into Rust, so a panic caught there is resumed once the
+    // call returns.
+
     pub fn has_property(&self, key: &JsString) -> Result<bool, JsError> {
-        let mut result = false;
-        let res = unsafe { JsObjectHasProperty(self.handle, key.handle, &mut result) };
-        JsError::assert(res)?;
+        let result = with_owning_context(self.handle, || {
+            let mut result = false;
+            let res = unsafe { JsObjectHasProperty(self.handle, key.handle, &mut result) };
+            JsError::assert(res)?;
 
-        Ok(result)
+            Ok(result)
+        });
+        JsRuntimeState::resume_current_panic();
+        result
     }
 
     pub fn set_property<T: Into<JsValue>>(
@@ -44,23 +56,74 @@ impl JsObject {
         value: T,
     ) -> Result<(), JsError> {
         let value = value.into();
-        let res = unsafe { JsObjectSetProperty(self.handle, key.handle, value.handle, true) };
-        JsError::assert(res)
+        let result = with_owning_context(self.handle, || {
+            let res = unsafe { JsObjectSetProperty(self.handle, key.handle, value.handle, true) };
+            JsError::assert(res)
+        });
+        JsRuntimeState::resume_current_panic();
+        result
     }
 
     pub fn get_property(&self, key: &JsString) -> Result<JsValue, JsError> {
-        let mut handle = ptr::null_mut();
-        let res = unsafe { JsObjectGetProperty(self.handle, key.handle, &mut handle) };
-        JsError::assert(res)?;
+        let result = with_owning_context(self.handle, || {
+            let mut handle = ptr::null_mut();
+            let res = unsafe { JsObjectGetProperty(self.handle, key.handle, &mut handle) };
+            JsError::assert(res)?;
 
-        Ok(JsValue { handle })
+            Ok(JsValue { handle })
+        });
+        JsRuntimeState::resume_current_panic();
+        result
     }
 
     pub fn delete_property(&self, key: &JsString) -> Result<bool, JsError> {
-        let mut handle = ptr::null_mut();
-        let res = unsafe { JsObjectDeleteProperty(self.handle, key.handle, true, &mut handle) };
+        let result = with_owning_context(self.handle, || {
+            let mut handle = ptr::null_mut();
+            let res = unsafe { JsObjectDeleteProperty(self.handle, key.handle, true, &mut handle) };
+            JsError::assert(res)?;
+            JsBoolean::try_from(JsValue { handle })?.try_into()
+        });
+        JsRuntimeState::resume_current_panic();
+        result
+    }
+
+    /// Calls the object as a function with `this` and `arguments`.
+    pub fn call(&self, this: &JsValue, arguments: &[JsValue]) -> Result<JsValue, JsError> {
+        let mut arguments: Vec<JsValueRef> = std::iter::once(this.handle)
+            .chain(arguments.iter().map(|argument| argument.handle))
+            .collect();
+        let argument_count =
+            c_ushort::try_from(arguments.len()).map_err(|_| JsError::InvalidArgument)?;
+
+        let result = with_owning_context(self.handle, || {
+            let mut result = ptr::null_mut();
+            let res = unsafe {
+                JsCallFunction(
+                    self.handle,
+                    arguments.as_mut_ptr(),
+                    argument_count,
+                    &mut result,
+                )
+            };
+            JsError::assert(res)?;
+
+            Ok(JsValue { handle: result })
+        });
+        JsRuntimeState::resume_current_panic();
+        result
+    }
+}
+
+/// Converts a value to an object, e.g. to call a function value with `JsObject::call`.
+impl TryFrom<JsValue> for JsObject {
+    type Error = JsError;
+
+    fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+        let mut result = ptr::null_mut();
+        let res = unsafe { JsConvertValueToObject(value.handle, &mut result) };
         JsError::assert(res)?;
-        JsBoolean::try_from(JsValue { handle })?.try_into()
+
+        Ok(JsObject { handle: result })
     }
 }
 
@@ -78,6 +141,7 @@ mod tests {
     use crate::context::JsScriptContext;
     use crate::number::JsNumber;
     use crate::runtime::JsRuntime;
+    use crate::script::JsScript;
 
     #[test]
     fn create_object() {
@@ -159,4 +223,45 @@ mod tests {
         assert_eq!(result, Ok(true));
         assert!(!global.has_property(&console_key).unwrap());
     }
+
+    #[test]
+    fn access_object_of_other_context() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let host = JsScriptContext::new(&mut runtime).unwrap();
+        let mut user = JsScriptContext::new(&mut runtime).unwrap();
+
+        let api = {
+            let _scope = host.enter().unwrap();
+            let script = JsScript::new(
+                "host",
+                "var hostOnly = true; \
+                 ({ value: 41, next() { return this.value + 1; }, realm: () => typeof hostOnly })",
+            )
+            .unwrap();
+            JsObject::try_from(runtime.run_script(&script).unwrap()).unwrap()
+        };
+
+        user.set_current_context().unwrap();
+        let this = JsValue { handle: api.handle };
+
+        let next = api.get_property(&JsString::new("next").unwrap()).unwrap();
+        let result = JsObject::try_from(next).unwrap().call(&this, &[]).unwrap();
+        assert_eq!(JsNumber::try_from(result).unwrap().try_into(), Ok(42));
+
+        // functions see the globals of the context they were created in
+        let realm = api.get_property(&JsString::new("realm").unwrap()).unwrap();
+        let result = JsObject::try_from(realm).unwrap().call(&this, &[]).unwrap();
+        assert_eq!(
+            JsString::try_from(result).unwrap().to_string(),
+            Ok("boolean".to_string())
+        );
+
+        // the user context is still current and doesn't see the host globals
+        assert_eq!(user.assert_current(), Ok(()));
+        let global = JsObject::global().unwrap();
+        assert_eq!(
+            global.has_property(&JsString::new("hostOnly").unwrap()),
+            Ok(false)
+        );
+    }
 }
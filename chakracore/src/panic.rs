@@ -0,0 +1,203 @@
+use crate::error::JsError;
+use crate::runtime::JsRuntimeState;
+use crate::string::JsString;
+use chakracore_sys::{JsCreateError, JsSetException, JsValueRef};
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::ptr;
+
+/// What happens when a Rust callback called by the engine panics.
+///
+/// Unwinding through the engine is undefined behaviour, so panics are always caught at the
+/// boundary. Callbacks that can't throw (garbage collection hooks, promise hooks, finalizers) hold
+/// on to the panic and resume it once the call into the engine that triggered them returns, unless
+/// the policy is `Abort`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum JsPanicPolicy {
+    /// Throws a JS `Error` with the panic message to the calling script.
+    #[default]
+    Throw,
+
+    /// Aborts the process.
+    Abort,
+
+    /// Throws a JS `Error` to unwind the calling script, then resumes the panic once the call into
+    /// the engine returns (e.g. `JsRuntime::run_script`).
+    Resume,
+}
+
+type Panic = Box<dyn Any + Send + 'static>;
+
+/// The message of a panic, as passed to `panic!`.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Rust callback panicked".to_string()
+    }
+}
+
+/// Runs a callback for which the engine doesn't expect an exception, returning `fallback` if it
+/// panics.
+///
+/// Without a runtime to hold on to the panic, the process is aborted.
+pub(crate) fn catch<R, F>(state: Option<&JsRuntimeState>, fallback: R, callback: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    match panic::catch_unwind(AssertUnwindSafe(callback)) {
+        Ok(result) => result,
+        Err(payload) => match state {
+            Some(state) if state.panic_policy != JsPanicPolicy::Abort => {
+                state.store_panic(payload);
+                fallback
+            }
+            _ => process::abort(),
+        },
+    }
+}
+
+/// Runs a callback returning a value to a script, throwing a JS `Error` if it panics.
+pub(crate) fn catch_and_throw<F>(callback: F) -> JsValueRef
+where
+    F: FnOnce() -> JsValueRef,
+{
+    let payload: Panic = match panic::catch_unwind(AssertUnwindSafe(callback)) {
+        Ok(result) => return result,
+        Err(payload) => payload,
+    };
+
+    let state = JsRuntimeState::current();
+    let policy = state
+        .as_ref()
+        .map_or(JsPanicPolicy::Throw, |state| state.panic_policy);
+    let message = panic_message(&*payload);
+
+    match (policy, state) {
+        (JsPanicPolicy::Abort, _) => process::abort(),
+        (JsPanicPolicy::Resume, Some(state)) => state.store_panic(payload),
+        _ => {}
+    }
+
+    throw_error(&message);
+    ptr::null_mut()
+}
+
+/// Sets a JS `Error` as the exception of the current context.
+pub(crate) fn throw_error(message: &str) {
+    let throw = || -> Result<(), JsError> {
+        let message = JsString::new(message)?;
+        let mut error = ptr::null_mut();
+        JsError::assert(unsafe { JsCreateError(message.handle, &mut error) })?;
+        JsError::assert(unsafe { JsSetException(error) })
+    };
+
+    // there is nothing left to report the failure to
+    let _ = throw();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::JsScriptContext;
+    use crate::function::JsFunction;
+    use crate::object::JsObject;
+    use crate::runtime::JsRuntime;
+    use crate::script::JsScript;
+
+    fn set_panicking_function(name: &str) {
+        let function = JsFunction::new(Box::new(|_| -> i32 { panic!("callback failed") })).unwrap();
+        let mut global = JsObject::global().unwrap();
+        global
+            .set_property(&JsString::new(name).unwrap(), function)
+            .unwrap();
+    }
+
+    #[test]
+    fn throw_panic_as_error() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+        set_panicking_function("boom");
+
+        let script = JsScript::new(
+            "test",
+            "(() => { try { boom(); } catch (e) { return e instanceof Error && e.message; } })()",
+        )
+        .unwrap();
+        let result = JsString::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(result.to_string(), Ok("callback failed".to_string()));
+
+        let script = JsScript::new("test", "boom()").unwrap();
+        assert_eq!(
+            runtime.run_script(&script).err(),
+            Some(JsError::ScriptException)
+        );
+    }
+
+    #[test]
+    fn resume_panic_after_script() {
+        let mut runtime = JsRuntime::builder()
+            .panic_policy(JsPanicPolicy::Resume)
+            .build()
+            .unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+        set_panicking_function("boom");
+
+        // the script can't swallow the panic
+        let script = JsScript::new("test", "try { boom(); } catch (e) {} 42").unwrap();
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _ = runtime.run_script(&script);
+        }))
+        .expect_err("the panic should be resumed");
+        assert_eq!(panic_message(&*payload), "callback failed");
+
+        // the runtime is still usable
+        let script = JsScript::new("test", "42").unwrap();
+        assert!(runtime.run_script(&script).is_ok());
+    }
+
+    #[test]
+    fn resume_panic_after_property_access() {
+        let mut runtime = JsRuntime::builder()
+            .panic_policy(JsPanicPolicy::Resume)
+            .build()
+            .unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+        set_panicking_function("boom");
+
+        let script = JsScript::new(
+            "test",
+            "Object.defineProperty(this, 'value', { get: boom });",
+        )
+        .unwrap();
+        runtime.run_script(&script).unwrap();
+
+        let global = JsObject::global().unwrap();
+        let key = JsString::new("value").unwrap();
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| global.get_property(&key).is_ok()))
+            .expect_err("the panic should be resumed");
+        assert_eq!(panic_message(&*payload), "callback failed");
+
+        // nothing is left to resume later
+        let script = JsScript::new("test", "42").unwrap();
+        assert!(runtime.run_script(&script).is_ok());
+    }
+
+    #[test]
+    fn resume_panic_from_gc_callback() {
+        let mut runtime = JsRuntime::builder()
+            .before_collect_callback(|| panic!("collect failed"))
+            .build()
+            .unwrap();
+
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| runtime.collect_garbage()))
+            .expect_err("the panic should be resumed");
+        assert_eq!(panic_message(&*payload), "collect failed");
+    }
+}
@@ -0,0 +1,245 @@
+#![allow(non_upper_case_globals)]
+
+use crate::error::JsError;
+use crate::object::JsObject;
+use crate::runtime::JsRuntimeState;
+use crate::string::JsString;
+use crate::undefined::undefined;
+use crate::value::JsValue;
+use chakracore_sys::{
+    _JsPromiseState_JsPromiseStateFulfilled, _JsPromiseState_JsPromiseStatePending, JsAddRef,
+    JsCreatePromise, JsGetPromiseResult, JsGetPromiseState, JsRelease, JsValueRef,
+};
+use std::fmt::{Debug, Formatter};
+use std::ptr;
+use std::rc::Rc;
+
+/// The state of a promise.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JsPromiseState {
+    Pending,
+    Fulfilled,
+    Rejected,
+}
+
+/// What happens to promises rejected without a handler, see
+/// `JsRuntimeBuilder::rejection_policy`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum JsRejectionPolicy {
+    /// Unhandled rejections are only reported to the promise rejection callback, if any.
+    #[default]
+    Ignore,
+
+    /// A rejection still unhandled once the job queue is drained fails the script that caused it
+    /// with `JsError::UnhandledRejection`.
+    Error,
+}
+
+#[derive(Debug)]
+pub struct JsPromise {
+    pub(crate) handle: JsValueRef,
+}
+
+impl JsPromise {
+    /// Create a pending promise, along with the functions settling it.
+    pub fn new() -> Result<(Self, JsResolver, JsRejecter), JsError> {
+        let runtime = JsRuntimeState::current().ok_or(JsError::NoCurrentContext)?;
+
+        let mut promise = ptr::null_mut();
+        let mut resolve = ptr::null_mut();
+        let mut reject = ptr::null_mut();
+        let res = unsafe { JsCreatePromise(&mut promise, &mut resolve, &mut reject) };
+        JsError::assert(res)?;
+
+        Ok((
+            Self { handle: promise },
+            JsResolver(SettleFunction::new(resolve, runtime.clone())?),
+            JsRejecter(SettleFunction::new(reject, runtime)?),
+        ))
+    }
+
+    /// Converts a value into a promise like `Promise.resolve`, adopting the state of thenables.
+    pub fn resolve<T: Into<JsValue>>(value: T) -> Result<Self, JsError> {
+        let global = JsObject::global()?;
+        let constructor = JsObject::try_from(global.get_property(&JsString::new("Promise")?)?)?;
+        let resolve = JsObject::try_from(constructor.get_property(&JsString::new("resolve")?)?)?;
+        let promise = resolve.call(&constructor.into(), &[value.into()])?;
+
+        JsPromise::try_from(promise)
+    }
+
+    pub fn state(&self) -> Result<JsPromiseState, JsError> {
+        let mut state = 0;
+        let res = unsafe { JsGetPromiseState(self.handle, &mut state) };
+        JsError::assert(res)?;
+
+        Ok(match state {
+            _JsPromiseState_JsPromiseStatePending => JsPromiseState::Pending,
+            _JsPromiseState_JsPromiseStateFulfilled => JsPromiseState::Fulfilled,
+            _ => JsPromiseState::Rejected,
+        })
+    }
+
+    /// The value the promise was fulfilled with, or the reason it was rejected with. Fails with
+    /// `JsError::PromisePending` if the promise isn't settled yet.
+    pub fn result(&self) -> Result<JsValue, JsError> {
+        let mut handle = ptr::null_mut();
+        let res = unsafe { JsGetPromiseResult(self.handle, &mut handle) };
+        JsError::assert(res)?;
+
+        Ok(JsValue { handle })
+    }
+}
+
+impl TryFrom<JsValue> for JsPromise {
+    type Error = JsError;
+
+    /// Fails with `JsError::InvalidArgument` if the value isn't a promise.
+    fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+        let promise = JsPromise {
+            handle: value.handle,
+        };
+        promise.state()?;
+
+        Ok(promise)
+    }
+}
+
+impl From<JsPromise> for JsValue {
+    fn from(promise: JsPromise) -> JsValue {
+        JsValue {
+            handle: promise.handle,
+        }
+    }
+}
+
+/// A resolve or reject function of a promise, kept alive until it's called or dropped.
+struct SettleFunction {
+    handle: JsValueRef,
+    runtime: Rc<JsRuntimeState>,
+}
+
+impl SettleFunction {
+    fn new(handle: JsValueRef, runtime: Rc<JsRuntimeState>) -> Result<Self, JsError> {
+        let res = unsafe { JsAddRef(handle, ptr::null_mut()) };
+        JsError::assert(res)?;
+
+        Ok(Self { handle, runtime })
+    }
+
+    fn call(&self, value: JsValue) -> Result<(), JsError> {
+        let function = JsObject::try_from(JsValue {
+            handle: self.handle,
+        })?;
+        function.call(&undefined()?, &[value])?;
+
+        Ok(())
+    }
+}
+
+impl Drop for SettleFunction {
+    fn drop(&mut self) {
+        // the function is gone with the runtime
+        if !self.runtime.disposed.get() {
+            unsafe { JsRelease(self.handle, ptr::null_mut()) };
+        }
+    }
+}
+
+/// Fulfills a promise created with `JsPromise::new`.
+pub struct JsResolver(SettleFunction);
+
+impl JsResolver {
+    /// Resolves the promise with `value`. Resolving with another promise makes the promise follow
+    /// it.
+    pub fn resolve<T: Into<JsValue>>(self, value: T) -> Result<(), JsError> {
+        self.0.call(value.into())
+    }
+}
+
+impl Debug for JsResolver {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsResolver")
+            .field("handle", &self.0.handle)
+            .finish()
+    }
+}
+
+/// Rejects a promise created with `JsPromise::new`.
+pub struct JsRejecter(SettleFunction);
+
+impl JsRejecter {
+    /// Rejects the promise with `reason`.
+    pub fn reject<T: Into<JsValue>>(self, reason: T) -> Result<(), JsError> {
+        self.0.call(reason.into())
+    }
+}
+
+impl Debug for JsRejecter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsRejecter")
+            .field("handle", &self.0.handle)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::JsScriptContext;
+    use crate::number::JsNumber;
+    use crate::runtime::JsRuntime;
+    use crate::script::JsScript;
+    use crate::string::JsString;
+
+    #[test]
+    fn resolve_promise() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let (promise, resolver, _) = JsPromise::new().unwrap();
+        assert_eq!(promise.state(), Ok(JsPromiseState::Pending));
+        assert_eq!(promise.result().err(), Some(JsError::PromisePending));
+
+        resolver.resolve(JsNumber::from(42)).unwrap();
+        assert_eq!(promise.state(), Ok(JsPromiseState::Fulfilled));
+        let result = JsNumber::try_from(promise.result().unwrap()).unwrap();
+        assert_eq!(result.try_into(), Ok(42));
+    }
+
+    #[test]
+    fn reject_promise() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let (promise, _, rejecter) = JsPromise::new().unwrap();
+        rejecter.reject(JsString::new("failed").unwrap()).unwrap();
+        assert_eq!(promise.state(), Ok(JsPromiseState::Rejected));
+
+        let reason = JsString::try_from(promise.result().unwrap()).unwrap();
+        assert_eq!(reason.to_string(), Ok("failed".to_string()));
+    }
+
+    #[test]
+    fn inspect_promise_from_script() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new("test", "Promise.resolve('done')").unwrap();
+        let promise = JsPromise::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(promise.state(), Ok(JsPromiseState::Fulfilled));
+
+        let script = JsScript::new("test", "({ then() {} })").unwrap();
+        let not_a_promise = JsPromise::try_from(runtime.run_script(&script).unwrap());
+        assert!(not_a_promise.is_err());
+    }
+
+    #[test]
+    fn create_promise_without_current_context() {
+        let _runtime = JsRuntime::new().unwrap();
+        assert_eq!(JsPromise::new().err(), Some(JsError::NoCurrentContext));
+    }
+}
@@ -1,15 +1,50 @@
 // TODO: maybe convert all bitflags to upper snake case
 #![allow(non_upper_case_globals)]
 
-use crate::error::JsError;
-use crate::script::JsScript;
+use crate::error::{JsError, JsModuleError};
+use crate::module::{self, JsModule, ModuleLoader};
+use crate::object::JsObject;
+use crate::panic::{catch, JsPanicPolicy};
+use crate::promise::JsRejectionPolicy;
+use crate::script::{JsParseOptions, JsParseScriptAttributes, JsScript};
+use crate::serialized::{external_buffer, JsSerializedScript};
+use crate::string::JsString;
+use crate::task::{LocalTask, Signal, Wakeup};
+use crate::thread_service::{self, JsThreadService};
+use crate::undefined::undefined;
 use crate::value::JsValue;
 use bitflags::bitflags;
 use chakracore_sys::{
-    JsCreateRuntime, JsDisposeRuntime, JsRun, JsRuntimeHandle,
-    _JsParseScriptAttributes_JsParseScriptAttributeNone,
+    _JsMemoryEventType_JsMemoryAllocate, _JsMemoryEventType_JsMemoryFree, JsAddRef,
+    JsCollectGarbage, JsContextRef, JsCreateRuntime, JsDisposeRuntime, JsGetAndClearException,
+    JsGetCurrentContext, JsGetRuntime, JsRelease, JsRun, JsRunScriptWithParserState,
+    JsRuntimeHandle, JsSetCurrentContext, JsSetHostPromiseRejectionTracker,
+    JsSetPromiseContinuationCallback, JsSetRuntimeBeforeCollectCallback,
+    JsSetRuntimeMemoryAllocationCallback, JsSetRuntimeMemoryLimit, JsValueRef,
 };
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::c_void;
+use std::future::Future;
+use std::os::raw::{c_uint, c_ulong};
+use std::panic;
+use std::pin::pin;
 use std::ptr;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+thread_local! {
+    /// The runtimes created on this thread by their handle, so callbacks without any state of
+    /// their own can find the runtime of the current context.
+    static RUNTIMES: RefCell<HashMap<usize, Weak<JsRuntimeState>>> = RefCell::new(HashMap::new());
+}
+
+/// The number of panics stored by all runtimes, so calls into the engine can check for one without
+/// looking up the runtime.
+static PENDING_PANICS: AtomicUsize = AtomicUsize::new(0);
 
 bitflags! {
     pub struct JsRuntimeAttributes: u32 {
@@ -48,8 +83,590 @@ bitflags! {
     }
 }
 
+/// The kind of memory event reported to a memory allocation callback.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JsMemoryEventType {
+    /// Indicates a request for memory allocation.
+    Allocate,
+
+    /// Indicates a memory freeing event.
+    Free,
+
+    /// Indicates a failed allocation event.
+    Failure,
+}
+
+type AllocationCallback = dyn Fn(JsMemoryEventType, usize) -> bool + Send + Sync;
+type BeforeCollectCallback = dyn Fn() + Send + Sync;
+type PromiseContinuationCallback = dyn Fn(JsValue) + Send + Sync;
+type PromiseRejectionCallback = dyn Fn(JsValue, JsValue, bool) + Send + Sync;
+
+/// Configuration for creating a `JsRuntime`.
+///
+/// The builder can be cloned and reused to create any number of identically configured runtimes.
+#[derive(Clone)]
+pub struct JsRuntimeBuilder {
+    attributes: JsRuntimeAttributes,
+    memory_limit: Option<usize>,
+    thread_service: Option<Arc<dyn JsThreadService>>,
+    allocation_callback: Option<Arc<AllocationCallback>>,
+    before_collect_callback: Option<Arc<BeforeCollectCallback>>,
+    promise_continuation_callback: Option<Arc<PromiseContinuationCallback>>,
+    promise_rejection_callback: Option<Arc<PromiseRejectionCallback>>,
+    module_loader: Option<Arc<dyn ModuleLoader>>,
+    parse_options: JsParseOptions,
+    panic_policy: JsPanicPolicy,
+    rejection_policy: JsRejectionPolicy,
+    auto_run_jobs: bool,
+}
+
+impl JsRuntimeBuilder {
+    /// Sets the runtime attributes.
+    pub fn attributes(mut self, attributes: JsRuntimeAttributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Sets the memory limit of the runtime in bytes. Any allocation exceeding the limit fails
+    /// with an out of memory error.
+    pub fn memory_limit(mut self, limit: usize) -> Self {
+        self.memory_limit = Some(limit);
+        self
+    }
+
+    /// Runs the background work of the runtime (garbage collection, JIT) on a host provided
+    /// executor instead of threads owned by the runtime.
+    ///
+    /// Each runtime hands its work to its own service. At most 32 distinct services can be in use
+    /// at once, runtimes sharing the same `Arc` count once. Building a runtime with yet another
+    /// service fails with `JsError::TooManyThreadServices`.
+    pub fn thread_service(mut self, service: Arc<dyn JsThreadService>) -> Self {
+        self.thread_service = Some(service);
+        self
+    }
+
+    /// Sets a callback that is called when the runtime allocates or frees memory. Returning
+    /// `false` from an allocation event fails the allocation.
+    pub fn allocation_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(JsMemoryEventType, usize) -> bool + Send + Sync + 'static,
+    {
+        self.allocation_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets a callback that is called before the runtime collects garbage.
+    pub fn before_collect_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.before_collect_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets a callback that is notified of the tasks queued by promises of every context created on
+    /// the runtime. The tasks are run by the job queue of the runtime, see `JsRuntime::run_jobs`.
+    pub fn promise_continuation_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(JsValue) + Send + Sync + 'static,
+    {
+        self.promise_continuation_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets a callback that is called with the promise, the rejection reason and whether the
+    /// rejection is handled whenever a promise of a context created on the runtime is rejected
+    /// without a handler, or a handler is added to an already rejected promise.
+    pub fn promise_rejection_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(JsValue, JsValue, bool) + Send + Sync + 'static,
+    {
+        self.promise_rejection_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets what happens to promises rejected without a handler. By default they are only reported
+    /// to the promise rejection callback.
+    pub fn rejection_policy(mut self, policy: JsRejectionPolicy) -> Self {
+        self.rejection_policy = policy;
+        self
+    }
+
+    /// Sets the loader of the ES modules run on the runtime with `JsRuntime::run_module` and of
+    /// the modules they import.
+    pub fn module_loader<L: ModuleLoader + 'static>(mut self, loader: L) -> Self {
+        self.module_loader = Some(Arc::new(loader));
+        self
+    }
+
+    /// Sets whether the job queue is drained after every script run on the runtime. Enabled by
+    /// default; when disabled, the host has to call `JsRuntime::run_jobs`.
+    ///
+    /// A job failing while the queue is drained automatically doesn't fail the script, it's
+    /// reported by the next call to `JsRuntime::run_jobs`.
+    pub fn auto_run_jobs(mut self, enabled: bool) -> Self {
+        self.auto_run_jobs = enabled;
+        self
+    }
+
+    /// Sets the parse options used by scripts run on the runtime.
+    pub fn parse_options(mut self, options: JsParseOptions) -> Self {
+        self.parse_options = options;
+        self
+    }
+
+    /// Sets what happens when a Rust callback called by the runtime panics.
+    pub fn panic_policy(mut self, policy: JsPanicPolicy) -> Self {
+        self.panic_policy = policy;
+        self
+    }
+
+    /// Checks that the configuration is consistent.
+    fn validate(&self) -> Result<(), JsError> {
+        if self.thread_service.is_some()
+            && self
+                .attributes
+                .contains(JsRuntimeAttributes::DisableBackgroundWork)
+        {
+            return Err(JsError::InvalidArgument);
+        }
+
+        if self.memory_limit == Some(0) {
+            return Err(JsError::InvalidArgument);
+        }
+
+        // the encoding and the strict mode directive are part of the source, so they can't be
+        // runtime wide defaults
+        if self.parse_options.strict
+            || self
+                .parse_options
+                .attributes
+                .contains(JsParseScriptAttributes::ArrayBufferIsUtf16Encoded)
+        {
+            return Err(JsError::InvalidArgument);
+        }
+
+        Ok(())
+    }
+
+    /// Create the `JsRuntime`
+    pub fn build(&self) -> Result<JsRuntime, JsError> {
+        self.validate()?;
+
+        let slot = match &self.thread_service {
+            Some(service) => Some(thread_service::register(service)?),
+            None => None,
+        };
+        let callback = slot.and_then(thread_service::callback);
+
+        let mut handle: JsRuntimeHandle = ptr::null_mut();
+        let res = unsafe { JsCreateRuntime(self.attributes.bits, callback, &mut handle) };
+        if let Err(error) = JsError::assert(res) {
+            if let Some(slot) = slot {
+                thread_service::unregister(slot);
+            }
+            return Err(error);
+        }
+
+        // from here on the runtime is disposed on failure
+        let runtime = JsRuntime {
+            handle,
+            state: Rc::new(JsRuntimeState {
+                allocation_callback: self.allocation_callback.clone(),
+                before_collect_callback: self.before_collect_callback.clone(),
+                promise_continuation_callback: self.promise_continuation_callback.clone(),
+                promise_rejection_callback: self.promise_rejection_callback.clone(),
+                module_loader: self.module_loader.clone(),
+                parse_options: self.parse_options,
+                panic_policy: self.panic_policy,
+                rejection_policy: self.rejection_policy,
+                unhandled_rejections: RefCell::new(Vec::new()),
+                pending_panic: Mutex::new(None),
+                auto_run_jobs: self.auto_run_jobs,
+                jobs: RefCell::new(VecDeque::new()),
+                running_jobs: Cell::new(false),
+                failed_job: RefCell::new(None),
+                tasks: RefCell::new(Vec::new()),
+                signal: Signal::new(),
+                finalized: RefCell::new(Vec::new()),
+                disposed: Cell::new(false),
+            }),
+            thread_service: slot,
+        };
+        RUNTIMES.with(|runtimes| {
+            runtimes
+                .borrow_mut()
+                .insert(handle as usize, Rc::downgrade(&runtime.state))
+        });
+
+        if let Some(limit) = self.memory_limit {
+            let res = unsafe { JsSetRuntimeMemoryLimit(handle, limit as c_ulong) };
+            JsError::assert(res)?;
+        }
+
+        let state = runtime.state_ptr();
+
+        if runtime.state.allocation_callback.is_some() {
+            let res = unsafe {
+                JsSetRuntimeMemoryAllocationCallback(handle, state, Some(allocation_callback))
+            };
+            JsError::assert(res)?;
+        }
+
+        if runtime.state.before_collect_callback.is_some() {
+            let res = unsafe {
+                JsSetRuntimeBeforeCollectCallback(handle, state, Some(before_collect_callback))
+            };
+            JsError::assert(res)?;
+        }
+
+        Ok(runtime)
+    }
+}
+
+/// Runtime configuration shared with the contexts of the runtime and the engine callbacks.
+pub(crate) struct JsRuntimeState {
+    allocation_callback: Option<Arc<AllocationCallback>>,
+    before_collect_callback: Option<Arc<BeforeCollectCallback>>,
+    promise_continuation_callback: Option<Arc<PromiseContinuationCallback>>,
+    promise_rejection_callback: Option<Arc<PromiseRejectionCallback>>,
+    pub(crate) module_loader: Option<Arc<dyn ModuleLoader>>,
+    pub(crate) parse_options: JsParseOptions,
+    pub(crate) panic_policy: JsPanicPolicy,
+    rejection_policy: JsRejectionPolicy,
+    /// The promises rejected without a handler and their reasons, tracked with the `Error`
+    /// rejection policy.
+    unhandled_rejections: RefCell<Vec<(JsValueRef, JsValueRef)>>,
+    /// A panic caught in a callback that couldn't be reported to the engine, resumed once the call
+    /// into the engine returns. The allocation callback may panic on a background thread of the
+    /// engine, hence the mutex.
+    pending_panic: Mutex<Option<Box<dyn Any + Send>>>,
+    auto_run_jobs: bool,
+    /// Tasks queued by promises, kept alive until they run.
+    jobs: RefCell<VecDeque<JsValueRef>>,
+    running_jobs: Cell<bool>,
+    /// The first job that failed while the queue was drained after a script.
+    failed_job: RefCell<Option<JsError>>,
+    /// Futures spawned on the runtime, e.g. by `JsFunction::new_async`.
+    tasks: RefCell<Vec<LocalTask>>,
+    signal: Arc<Signal>,
+    /// Owners of external buffers collected by the engine. They may hold handles, which can't be
+    /// released while the engine is collecting, so they are dropped after the next script or
+    /// collection.
+    pub(crate) finalized: RefCell<Vec<Box<dyn Any>>>,
+    /// Set once the runtime is disposed, after which handles must no longer be released.
+    pub(crate) disposed: Cell<bool>,
+}
+
+impl JsRuntimeState {
+    /// The state of the runtime of the current context.
+    pub(crate) fn current() -> Option<Rc<JsRuntimeState>> {
+        let mut context = ptr::null_mut();
+        JsError::assert(unsafe { JsGetCurrentContext(&mut context) }).ok()?;
+        if context.is_null() {
+            return None;
+        }
+
+        let mut handle = ptr::null_mut();
+        JsError::assert(unsafe { JsGetRuntime(context, &mut handle) }).ok()?;
+        RUNTIMES.with(|runtimes| {
+            runtimes
+                .borrow()
+                .get(&(handle as usize))
+                .and_then(Weak::upgrade)
+        })
+    }
+
+    /// Holds on to a panic until `resume_panic` is called. Only the first panic is kept.
+    ///
+    /// This may be called from any thread.
+    pub(crate) fn store_panic(&self, payload: Box<dyn Any + Send>) {
+        let mut pending = self
+            .pending_panic
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if pending.is_none() {
+            *pending = Some(payload);
+            PENDING_PANICS.fetch_add(1, atomic::Ordering::SeqCst);
+        }
+    }
+
+    /// Resumes a panic caught in a callback, clearing the exception thrown to unwind the script.
+    pub(crate) fn resume_panic(&self) {
+        let payload = self
+            .pending_panic
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(payload) = payload {
+            PENDING_PANICS.fetch_sub(1, atomic::Ordering::SeqCst);
+            let mut exception = ptr::null_mut();
+            unsafe { JsGetAndClearException(&mut exception) };
+            panic::resume_unwind(payload);
+        }
+    }
+
+    /// Resumes a panic caught in a callback of the runtime of the current context, for calls into
+    /// the engine that aren't made through the runtime (e.g. `JsObject::call`).
+    pub(crate) fn resume_current_panic() {
+        if PENDING_PANICS.load(atomic::Ordering::SeqCst) == 0 {
+            return;
+        }
+
+        if let Some(state) = JsRuntimeState::current() {
+            state.resume_panic();
+        }
+    }
+
+    /// Runs the queued jobs and the woken tasks until there's nothing left to run, including jobs
+    /// queued while running. Does nothing if the jobs are already being run further up the stack.
+    pub(crate) fn run_jobs(&self) -> Result<(), JsError> {
+        if self.disposed.get() || self.running_jobs.replace(true) {
+            return Ok(());
+        }
+
+        // reset and released even if a job or task panics
+        struct Running<'a>(&'a Cell<bool>);
+        impl Drop for Running<'_> {
+            fn drop(&mut self) {
+                self.0.set(false);
+            }
+        }
+        struct Job(JsValueRef);
+        impl Drop for Job {
+            fn drop(&mut self) {
+                unsafe { JsRelease(self.0, ptr::null_mut()) };
+            }
+        }
+        let _running = Running(&self.running_jobs);
+
+        loop {
+            let job = self.jobs.borrow_mut().pop_front();
+            if let Some(job) = job {
+                let job = Job(job);
+                let result = JsObject::try_from(JsValue { handle: job.0 })
+                    .and_then(|job| job.call(&undefined()?, &[]));
+                self.resume_panic();
+                result.map_err(job_error)?;
+            } else if !self.poll_tasks() {
+                return Ok(());
+            } else {
+                // e.g. a module evaluated by a task
+                self.resume_panic();
+            }
+        }
+    }
+
+    /// Polls the woken tasks once, returning whether any was woken.
+    fn poll_tasks(&self) -> bool {
+        // tasks may spawn other tasks while polled
+        let tasks = std::mem::take(&mut *self.tasks.borrow_mut());
+        let (woken, pending): (Vec<_>, Vec<_>) = tasks.into_iter().partition(LocalTask::is_woken);
+        self.tasks.borrow_mut().extend(pending);
+
+        let polled = !woken.is_empty();
+        for mut task in woken {
+            if !task.poll() {
+                self.tasks.borrow_mut().push(task);
+            }
+        }
+
+        polled
+    }
+
+    pub(crate) fn has_jobs(&self) -> bool {
+        !self.jobs.borrow().is_empty() || self.tasks.borrow().iter().any(LocalTask::is_woken)
+    }
+
+    /// Spawns a future polled along with the job queue.
+    pub(crate) fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let task = LocalTask::new(future, self.signal.clone());
+        self.tasks.borrow_mut().push(task);
+    }
+
+    /// Drops the owners of the external buffers collected since the last call.
+    pub(crate) fn drop_finalized(&self) {
+        drop(self.finalized.take());
+    }
+
+    /// Drains the job queue after a script ran, unless disabled.
+    pub(crate) fn after_run(&self) -> Result<(), JsError> {
+        self.drop_finalized();
+        if self.auto_run_jobs {
+            if let Err(error) = self.run_jobs() {
+                self.failed_job.borrow_mut().get_or_insert(error);
+            }
+            self.check_rejections()?;
+        }
+
+        Ok(())
+    }
+
+    /// Keeps track of a promise rejected without a handler, or forgets it once a handler is added.
+    fn track_rejection(&self, promise: JsValueRef, reason: JsValueRef, handled: bool) {
+        let mut unhandled = self.unhandled_rejections.borrow_mut();
+        if handled {
+            if let Some(index) = unhandled.iter().position(|&(p, _)| p == promise) {
+                let (promise, reason) = unhandled.remove(index);
+                unsafe {
+                    JsRelease(promise, ptr::null_mut());
+                    JsRelease(reason, ptr::null_mut());
+                }
+            }
+        } else if unsafe { JsAddRef(promise, ptr::null_mut()) } == 0 {
+            if unsafe { JsAddRef(reason, ptr::null_mut()) } == 0 {
+                unhandled.push((promise, reason));
+            } else {
+                unsafe { JsRelease(promise, ptr::null_mut()) };
+            }
+        }
+    }
+
+    /// Fails with the first rejection left unhandled since the last check, if any.
+    pub(crate) fn check_rejections(&self) -> Result<(), JsError> {
+        let unhandled = self.unhandled_rejections.take();
+        let reason = unhandled.first().map(|&(_, reason)| {
+            JsString::try_from(JsValue { handle: reason })
+                .and_then(|reason| reason.to_string())
+                .unwrap_or_else(|error| error.to_string())
+        });
+
+        for (promise, reason) in unhandled {
+            unsafe {
+                JsRelease(promise, ptr::null_mut());
+                JsRelease(reason, ptr::null_mut());
+            }
+        }
+
+        match reason {
+            Some(reason) => Err(JsError::UnhandledRejection { reason }),
+            None => Ok(()),
+        }
+    }
+
+    /// Registers the per context callbacks on a newly created context.
+    pub(crate) fn attach(self: &Rc<Self>, context: JsContextRef) -> Result<(), JsError> {
+        let state = Rc::as_ptr(self) as *mut c_void;
+
+        // the promise and module callbacks are registered on the current context
+        let mut previous = ptr::null_mut();
+        JsError::assert(unsafe { JsGetCurrentContext(&mut previous) })?;
+        JsError::assert(unsafe { JsSetCurrentContext(context) })?;
+
+        let mut res = JsError::assert(unsafe {
+            JsSetPromiseContinuationCallback(Some(promise_continuation_callback), state)
+        });
+
+        if self.promise_rejection_callback.is_some()
+            || self.rejection_policy == JsRejectionPolicy::Error
+        {
+            res = res.and_then(|_| {
+                JsError::assert(unsafe {
+                    JsSetHostPromiseRejectionTracker(Some(promise_rejection_callback), state)
+                })
+            });
+        }
+
+        if self.module_loader.is_some() {
+            res = res.and_then(|_| module::attach());
+        }
+
+        JsError::assert(unsafe { JsSetCurrentContext(previous) })?;
+        res
+    }
+}
+
+/// Clears the exception thrown by a failing job, so the engine can run the remaining jobs.
+fn job_error(error: JsError) -> JsError {
+    if error != JsError::ScriptException {
+        return error;
+    }
+
+    let mut exception = ptr::null_mut();
+    if JsError::assert(unsafe { JsGetAndClearException(&mut exception) }).is_err() {
+        return error;
+    }
+
+    let message = JsString::try_from(JsValue { handle: exception })
+        .and_then(|message| message.to_string())
+        .unwrap_or_else(|error| {
+            // `toString` threw as well
+            unsafe { JsGetAndClearException(&mut exception) };
+            error.to_string()
+        });
+    JsError::JobException { message }
+}
+
+unsafe extern "C" fn allocation_callback(
+    callback_state: *mut c_void,
+    allocation_event: c_uint,
+    allocation_size: c_ulong,
+) -> bool {
+    // this may run on a background thread of the engine, so only the fields that are safe to
+    // share are used
+    let state = &*(callback_state as *const JsRuntimeState);
+    let event = match allocation_event {
+        _JsMemoryEventType_JsMemoryAllocate => JsMemoryEventType::Allocate,
+        _JsMemoryEventType_JsMemoryFree => JsMemoryEventType::Free,
+        _ => JsMemoryEventType::Failure,
+    };
+
+    catch(Some(state), true, || match &state.allocation_callback {
+        Some(callback) => callback(event, allocation_size as usize),
+        None => true,
+    })
+}
+
+unsafe extern "C" fn before_collect_callback(callback_state: *mut c_void) {
+    let state = &*(callback_state as *const JsRuntimeState);
+    if let Some(callback) = &state.before_collect_callback {
+        catch(Some(state), (), || callback());
+    }
+}
+
+unsafe extern "C" fn promise_continuation_callback(task: JsValueRef, callback_state: *mut c_void) {
+    let state = &*(callback_state as *const JsRuntimeState);
+
+    // without a reference the task would be collected before it runs
+    if JsError::assert(JsAddRef(task, ptr::null_mut())).is_ok() {
+        state.jobs.borrow_mut().push_back(task);
+    }
+
+    if let Some(callback) = &state.promise_continuation_callback {
+        catch(Some(state), (), || callback(JsValue { handle: task }));
+    }
+}
+
+unsafe extern "C" fn promise_rejection_callback(
+    promise: JsValueRef,
+    reason: JsValueRef,
+    handled: bool,
+    callback_state: *mut c_void,
+) {
+    let state = &*(callback_state as *const JsRuntimeState);
+    if state.rejection_policy == JsRejectionPolicy::Error {
+        state.track_rejection(promise, reason, handled);
+    }
+
+    if let Some(callback) = &state.promise_rejection_callback {
+        catch(Some(state), (), || {
+            callback(
+                JsValue { handle: promise },
+                JsValue { handle: reason },
+                handled,
+            )
+        });
+    }
+}
+
 pub struct JsRuntime {
     pub(crate) handle: JsRuntimeHandle,
+    pub(crate) state: Rc<JsRuntimeState>,
+    /// The slot of the thread service of the runtime, if any.
+    thread_service: Option<usize>,
 }
 
 impl JsRuntime {
@@ -60,36 +677,180 @@ impl JsRuntime {
 
     /// Create a new `JsRuntime` with attributes
     pub fn with_attributes(attributes: JsRuntimeAttributes) -> Result<Self, JsError> {
-        let mut runtime: JsRuntimeHandle = ptr::null_mut();
-        let res = unsafe { JsCreateRuntime(attributes.bits, None, &mut runtime) };
-        JsError::assert(res)?;
+        JsRuntime::builder().attributes(attributes).build()
+    }
+
+    /// Create a builder to configure a new `JsRuntime`
+    pub fn builder() -> JsRuntimeBuilder {
+        JsRuntimeBuilder {
+            attributes: JsRuntimeAttributes::None,
+            memory_limit: None,
+            thread_service: None,
+            allocation_callback: None,
+            before_collect_callback: None,
+            promise_continuation_callback: None,
+            promise_rejection_callback: None,
+            module_loader: None,
+            parse_options: JsParseOptions::new(),
+            panic_policy: JsPanicPolicy::default(),
+            rejection_policy: JsRejectionPolicy::default(),
+            auto_run_jobs: true,
+        }
+    }
+
+    fn state_ptr(&self) -> *mut c_void {
+        Rc::as_ptr(&self.state) as *mut c_void
+    }
 
-        Ok(Self { handle: runtime })
+    /// Performs a full garbage collection.
+    pub fn collect_garbage(&mut self) -> Result<(), JsError> {
+        let res = unsafe { JsCollectGarbage(self.handle) };
+        self.state.drop_finalized();
+        self.state.resume_panic();
+        JsError::assert(res)
     }
 
     pub fn run_script(&mut self, script: &JsScript) -> Result<JsValue, JsError> {
+        let options = script.parse_options(&self.state);
         let mut result = ptr::null_mut();
         let res = unsafe {
             JsRun(
                 script.handle,
-                0_usize,
+                options.source_context,
                 script.source_url.handle,
-                _JsParseScriptAttributes_JsParseScriptAttributeNone,
+                options.attributes.bits(),
                 &mut result,
             )
         };
+        self.state.resume_panic();
         JsError::assert(res)?;
+        self.state.after_run()?;
 
         Ok(JsValue { handle: result })
     }
+
+    /// Runs a script with a parser state serialized with `JsScript::serialize_parser_state`,
+    /// skipping the initial scan of the source.
+    pub fn run_script_with_parser_state(
+        &mut self,
+        script: &JsScript,
+        parser_state: &[u8],
+    ) -> Result<JsValue, JsError> {
+        if parser_state.is_empty() {
+            return Err(JsError::BadSerializedScript);
+        }
+
+        let options = script.parse_options(&self.state);
+        let parser_state = external_buffer(parser_state.to_vec())?;
+        let mut result = ptr::null_mut();
+        let res = unsafe {
+            JsRunScriptWithParserState(
+                script.handle,
+                options.source_context,
+                script.source_url.handle,
+                options.attributes.bits(),
+                parser_state,
+                &mut result,
+            )
+        };
+        self.state.resume_panic();
+        JsError::assert(res)?;
+        self.state.after_run()?;
+
+        Ok(JsValue { handle: result })
+    }
+
+    /// Runs a serialized script. A buffer serialized by a different version of the engine is
+    /// reported as `JsError::BadSerializedScript`.
+    pub fn run_serialized(&mut self, script: JsSerializedScript) -> Result<JsValue, JsError> {
+        let result = script.run(self)?;
+        self.state.after_run()?;
+
+        Ok(result)
+    }
+
+    /// Runs the ES module identified by `specifier` in the current context, loading it and its
+    /// imports with the module loader of the runtime. A module is only evaluated once per context,
+    /// running it again returns the same module.
+    ///
+    /// `import.meta` isn't populated by the host, ChakraCore 1.11 has no callback to initialize it.
+    pub fn run_module(&mut self, specifier: &str) -> Result<JsModule, JsModuleError> {
+        let result = module::run(&self.state, specifier);
+        self.state.resume_panic();
+        let module = result?;
+        self.state.after_run()?;
+
+        Ok(module)
+    }
+
+    /// Runs the jobs queued by promises (e.g. `then` callbacks) until the queue is empty. This is
+    /// done automatically after every script unless disabled with `JsRuntimeBuilder::auto_run_jobs`.
+    ///
+    /// A failing job stops the queue and is reported as `JsError::JobException`, the remaining
+    /// jobs run on the next call. A job that failed while the queue was drained after a script is
+    /// reported first, before any job runs. With the `Error` rejection policy, a promise left
+    /// rejected without a handler fails the call.
+    pub fn run_jobs(&mut self) -> Result<(), JsError> {
+        if let Some(error) = self.state.failed_job.take() {
+            return Err(error);
+        }
+
+        self.state.run_jobs()?;
+        self.state.check_rejections()
+    }
+
+    /// Runs a future to completion on the current thread, running the job queue and the tasks
+    /// spawned on the runtime whenever the future is pending. This drives futures awaiting
+    /// promises, e.g. `JsPromise::into_future`, and async functions (`JsFunction::new_async`).
+    ///
+    /// Once there's nothing left to run, the thread is parked until the future or a task is woken.
+    pub fn block_on<F: Future>(&mut self, future: F) -> Result<F::Output, JsError> {
+        let mut future = pin!(future);
+        let wakeup = Wakeup::new(self.state.signal.clone());
+        let waker = Waker::from(wakeup.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            if wakeup.take() {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return Ok(output);
+                }
+            }
+
+            if self.state.has_jobs() {
+                self.state.run_jobs()?;
+            } else if !wakeup.is_woken() {
+                self.state.signal.wait();
+            }
+        }
+    }
 }
 
 impl Drop for JsRuntime {
     fn drop(&mut self) {
+        // finalizers run while the runtime is disposed must not release handles either
+        self.state.disposed.set(true);
         unsafe {
             let res = JsDisposeRuntime(self.handle);
             JsError::assert(res).expect("Failed to dispose runtime.");
         }
+        self.state.jobs.borrow_mut().clear();
+        drop(self.state.tasks.take());
+        self.state.unhandled_rejections.borrow_mut().clear();
+        self.state.drop_finalized();
+        if let Ok(Some(_)) = self
+            .state
+            .pending_panic
+            .lock()
+            .map(|mut pending| pending.take())
+        {
+            PENDING_PANICS.fetch_sub(1, atomic::Ordering::SeqCst);
+        }
+        RUNTIMES.with(|runtimes| runtimes.borrow_mut().remove(&(self.handle as usize)));
+
+        if let Some(slot) = self.thread_service.take() {
+            thread_service::unregister(slot);
+        }
     }
 }
 
@@ -100,6 +861,7 @@ mod tests {
     use crate::context::JsScriptContext;
     use crate::number::JsNumber;
     use crate::string::JsString;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[test]
     fn create_runtime() {
@@ -107,6 +869,245 @@ mod tests {
         assert_eq!(runtime.map(|x| x.handle.is_null()), Ok(false));
     }
 
+    #[test]
+    fn create_runtime_with_builder() {
+        let runtime = JsRuntime::builder()
+            .attributes(JsRuntimeAttributes::DisableEval)
+            .build();
+        assert_eq!(runtime.map(|x| x.handle.is_null()), Ok(false));
+    }
+
+    #[test]
+    fn reuse_builder() {
+        let builder = JsRuntime::builder()
+            .attributes(JsRuntimeAttributes::DisableEval)
+            .memory_limit(64 * 1024 * 1024);
+
+        let first = builder.build();
+        let second = builder.clone().build();
+        assert!(first.is_ok());
+        assert!(second.is_ok());
+    }
+
+    #[test]
+    fn reject_invalid_configuration() {
+        let thread_service = JsRuntime::builder()
+            .attributes(JsRuntimeAttributes::DisableBackgroundWork)
+            .thread_service(Arc::new(|_| false))
+            .build();
+        assert_eq!(thread_service.err(), Some(JsError::InvalidArgument));
+
+        let memory_limit = JsRuntime::builder().memory_limit(0).build();
+        assert_eq!(memory_limit.err(), Some(JsError::InvalidArgument));
+
+        let parse_options = JsRuntime::builder()
+            .parse_options(
+                JsParseOptions::new()
+                    .attributes(JsParseScriptAttributes::ArrayBufferIsUtf16Encoded),
+            )
+            .build();
+        assert_eq!(parse_options.err(), Some(JsError::InvalidArgument));
+
+        let strict = JsRuntime::builder()
+            .parse_options(JsParseOptions::new().strict(true))
+            .build();
+        assert_eq!(strict.err(), Some(JsError::InvalidArgument));
+    }
+
+    #[test]
+    fn run_script_exceeding_memory_limit() {
+        let mut runtime = JsRuntime::builder()
+            .memory_limit(8 * 1024 * 1024)
+            .build()
+            .unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new(
+            "test",
+            "(() => { var a = []; while (true) { a.push(new Array(1024)); } })()",
+        )
+        .unwrap();
+        assert!(runtime.run_script(&script).is_err());
+    }
+
+    #[test]
+    fn allocation_and_before_collect_callbacks() {
+        let allocations = Arc::new(AtomicUsize::new(0));
+        let collections = Arc::new(AtomicUsize::new(0));
+
+        let allocation_counter = allocations.clone();
+        let collection_counter = collections.clone();
+        let mut runtime = JsRuntime::builder()
+            .allocation_callback(move |event, _| {
+                if event == JsMemoryEventType::Allocate {
+                    allocation_counter.fetch_add(1, Ordering::SeqCst);
+                }
+                true
+            })
+            .before_collect_callback(move || {
+                collection_counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .build()
+            .unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script =
+            JsScript::new("test", "(() => { return new Array(1024).fill(1); })()").unwrap();
+        runtime.run_script(&script).unwrap();
+        runtime.collect_garbage().unwrap();
+
+        assert!(allocations.load(Ordering::SeqCst) > 0);
+        assert!(collections.load(Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn promise_callbacks() {
+        let tasks = Arc::new(AtomicUsize::new(0));
+        let rejections = Arc::new(AtomicUsize::new(0));
+
+        let task_counter = tasks.clone();
+        let rejection_counter = rejections.clone();
+        let mut runtime = JsRuntime::builder()
+            .promise_continuation_callback(move |_| {
+                task_counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .promise_rejection_callback(move |_, _, handled| {
+                if !handled {
+                    rejection_counter.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build()
+            .unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new(
+            "test",
+            "Promise.resolve(1).then(() => {}); Promise.reject(new Error('rejected'));",
+        )
+        .unwrap();
+        runtime.run_script(&script).unwrap();
+
+        assert_eq!(tasks.load(Ordering::SeqCst), 1);
+        assert_eq!(rejections.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn run_promise_jobs_after_script() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new(
+            "test",
+            "var log = []; \
+             Promise.resolve().then(() => log.push(1)).then(() => log.push(3)); \
+             log.push(2);",
+        )
+        .unwrap();
+        runtime.run_script(&script).unwrap();
+
+        let script = JsScript::new("test", "log.join()").unwrap();
+        let result = JsString::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(result.to_string(), Ok("2,1,3".to_string()));
+    }
+
+    #[test]
+    fn run_promise_jobs_manually() {
+        let mut runtime = JsRuntime::builder().auto_run_jobs(false).build().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new(
+            "test",
+            "var done = false; Promise.resolve().then(() => done = true);",
+        )
+        .unwrap();
+        runtime.run_script(&script).unwrap();
+
+        let done = JsScript::new("test", "done").unwrap();
+        let result = runtime.run_script(&done).unwrap();
+        assert_eq!(JsBoolean::try_from(result).unwrap().try_into(), Ok(false));
+
+        runtime.run_jobs().unwrap();
+        let result = runtime.run_script(&done).unwrap();
+        assert_eq!(JsBoolean::try_from(result).unwrap().try_into(), Ok(true));
+
+        // nothing left to run
+        runtime.run_jobs().unwrap();
+    }
+
+    #[test]
+    fn report_failing_jobs_separately() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        // promise reactions catch their exceptions, so the failing job is queued directly
+        let script = JsScript::new(
+            "test",
+            "var done = false; \
+             [() => { throw new Error('job failed'); }, () => { done = true; }]",
+        )
+        .unwrap();
+        let jobs = JsObject::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        for index in ["0", "1"] {
+            let job = jobs.get_property(&JsString::new(index).unwrap()).unwrap();
+            JsError::assert(unsafe { JsAddRef(job.handle, ptr::null_mut()) }).unwrap();
+            runtime.state.jobs.borrow_mut().push_back(job.handle);
+        }
+
+        // the script still succeeds, the failure is reported by the next call
+        let script = JsScript::new("test", "42").unwrap();
+        let result = JsNumber::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(result.try_into(), Ok(42));
+        assert_eq!(
+            runtime.run_jobs(),
+            Err(JsError::JobException {
+                message: "Error: job failed".to_string()
+            })
+        );
+
+        // the exception was cleared, so the remaining job runs
+        runtime.run_jobs().unwrap();
+        let done = JsScript::new("test", "done").unwrap();
+        let result = runtime.run_script(&done).unwrap();
+        assert_eq!(JsBoolean::try_from(result).unwrap().try_into(), Ok(true));
+    }
+
+    #[test]
+    fn fail_script_on_unhandled_rejection() {
+        let mut runtime = JsRuntime::builder()
+            .rejection_policy(JsRejectionPolicy::Error)
+            .build()
+            .unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new(
+            "test",
+            "(async () => { await null; throw new Error('failed'); })()",
+        )
+        .unwrap();
+        assert_eq!(
+            runtime.run_script(&script).err(),
+            Some(JsError::UnhandledRejection {
+                reason: "Error: failed".to_string()
+            })
+        );
+
+        // rejections handled before the job queue is drained don't count
+        let script = JsScript::new(
+            "test",
+            "var p = Promise.reject(1); \
+             Promise.resolve().then(() => p.catch(() => {}));",
+        )
+        .unwrap();
+        assert!(runtime.run_script(&script).is_ok());
+    }
+
     #[test]
     fn run_script() {
         let mut runtime = JsRuntime::new().unwrap();
@@ -153,6 +1154,27 @@ mod tests {
         assert_eq!(res, Ok(1.23));
     }
 
+    #[test]
+    fn run_script_with_parser_state() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let source = "(() => { return 'parsed ' + 'once'; })()";
+        let parser_state = JsScript::new("test", source)
+            .unwrap()
+            .serialize_parser_state()
+            .unwrap();
+        assert!(!parser_state.is_empty());
+
+        let script = JsScript::new("test", source).unwrap();
+        let result = runtime
+            .run_script_with_parser_state(&script, &parser_state)
+            .unwrap();
+        let s = JsString::try_from(result).unwrap();
+        assert_eq!(s.to_string().unwrap(), "parsed once".to_string());
+    }
+
     #[test]
     fn run_script_with_bool_result() {
         let mut runtime = JsRuntime::new().unwrap();
@@ -1,53 +1,510 @@
-use crate::error::JsError;
+// TODO: maybe convert all bitflags to upper snake case
+#![allow(non_upper_case_globals)]
+
+use crate::context::JsScriptContext;
+use crate::error::{JsError, JsSourceError};
+use crate::number::JsNumber;
+use crate::object::JsObject;
+use crate::runtime::JsRuntimeState;
+use crate::serialized::external_buffer;
 use crate::string::JsString;
-use chakracore_sys::{JsCreateExternalArrayBuffer, JsValueRef};
-use std::ffi::CString;
+use crate::value::JsValue;
+use bitflags::bitflags;
+use chakracore_sys::{
+    JsAddRef, JsCallFunction, JsCreateExternalArrayBuffer, JsGetAndClearExceptionWithMetadata,
+    JsGetArrayBufferStorage, JsGetUndefinedValue, JsParse, JsRelease, JsSerialize,
+    JsSerializeParserState, JsSourceContext, JsValueRef,
+};
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::os::raw::{c_uint, c_ushort};
+use std::path::Path;
 use std::ptr;
+use std::rc::Rc;
+use std::slice;
+use std::str;
+
+bitflags! {
+    pub struct JsParseScriptAttributes: u32 {
+        /// No special attributes.
+        const None = 0;
+
+        /// Specified script is internal and non-user code. Hidden from debugger.
+        const LibraryCode = 1;
+
+        /// ChakraCore assumes ExternalArrayBuffer is Utf8 by default. This one needs to be set for
+        /// Utf16.
+        const ArrayBufferIsUtf16Encoded = 2;
+    }
+}
+
+/// Options used when parsing a script.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct JsParseOptions {
+    pub(crate) attributes: JsParseScriptAttributes,
+    pub(crate) source_context: JsSourceContext,
+    pub(crate) strict: bool,
+}
+
+impl JsParseOptions {
+    /// Create the default parse options
+    pub fn new() -> Self {
+        Self {
+            attributes: JsParseScriptAttributes::None,
+            source_context: 0,
+            strict: false,
+        }
+    }
+
+    /// Sets the parse attributes.
+    pub fn attributes(mut self, attributes: JsParseScriptAttributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Marks the script as host code, which is hidden from the debugger and stack traces.
+    pub fn library_code(mut self, library_code: bool) -> Self {
+        self.attributes
+            .set(JsParseScriptAttributes::LibraryCode, library_code);
+        self
+    }
+
+    /// Sets the cookie identifying the source of the script, which is reported back by the
+    /// debugger and profiler.
+    pub fn source_context(mut self, source_context: JsSourceContext) -> Self {
+        self.source_context = source_context;
+        self
+    }
+
+    /// Runs the script in strict mode by inserting a `"use strict";` directive into the source, as
+    /// the engine has no parse attribute for it.
+    ///
+    /// The directive goes on the first line, after a byte order mark or a hashbang line, so line
+    /// numbers are unaffected. Columns on that line are shifted in errors and stack traces, and the
+    /// directive is part of the source the engine reports (e.g. to the debugger).
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+}
+
+impl Default for JsParseOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const STRICT_DIRECTIVE: &str = "\"use strict\";";
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Where the strict mode directive is inserted: after a byte order mark and a hashbang line, which
+/// have to stay at the start of the source.
+fn directive_offset(script: &[u8], utf16: bool) -> usize {
+    let width = if utf16 { 2 } else { 1 };
+    let unit = |offset: usize| -> Option<u16> {
+        if utf16 {
+            let unit = script.get(offset..offset + 2)?;
+            Some(u16::from_le_bytes([unit[0], unit[1]]))
+        } else {
+            script.get(offset).map(|&byte| u16::from(byte))
+        }
+    };
+
+    let mut offset = 0;
+    if unit(0) == Some(0xFEFF) {
+        offset = width;
+    } else if !utf16 && script.starts_with(UTF8_BOM) {
+        offset = UTF8_BOM.len();
+    }
+
+    if unit(offset) == Some(u16::from(b'#')) && unit(offset + width) == Some(u16::from(b'!')) {
+        while let Some(unit) = unit(offset) {
+            offset += width;
+            if unit == u16::from(b'\n') {
+                break;
+            }
+        }
+    }
+
+    offset
+}
+
+/// Checks that a source matches the encoding given by its parse attributes.
+fn check_encoding(script: &[u8], attributes: JsParseScriptAttributes) -> Result<(), JsError> {
+    let valid = if attributes.contains(JsParseScriptAttributes::ArrayBufferIsUtf16Encoded) {
+        script.chunks_exact(2).remainder().is_empty()
+    } else {
+        str::from_utf8(script).is_ok()
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(JsError::InvalidArgument)
+    }
+}
 
-#[derive(Debug)]
 pub struct JsScript {
     pub(crate) handle: JsValueRef,
     pub(crate) source_url: JsString,
-    raw: *mut i8,
+    options: Option<JsParseOptions>,
+    runtime: Rc<JsRuntimeState>,
 }
 
 impl JsScript {
-    #[allow(clippy::cast_possible_truncation)]
-    /// Convert a string into a script
+    /// Convert a string into a script, parsed with the parse options of the runtime.
+    ///
+    /// The script must be valid UTF-8. A `Vec<u8>` or `String` is handed to the engine without
+    /// copying.
     pub fn new<TUrl: Into<Vec<u8>>, TScript: Into<Vec<u8>>>(
         url: TUrl,
         script: TScript,
     ) -> Result<Self, JsError> {
-        let script = CString::new(script).unwrap();
-        let size = script.as_bytes().len();
-        let script = script.into_raw();
-        let mut source = ptr::null_mut();
+        let script = script.into();
+        check_encoding(&script, JsParseScriptAttributes::None)?;
+        JsScript::create(url, script, None)
+    }
+
+    /// Convert a string into a script, parsed with `options` instead of the parse options of the
+    /// runtime.
+    ///
+    /// If `options` contain `JsParseScriptAttributes::ArrayBufferIsUtf16Encoded`, the script must
+    /// be UTF-16 in little endian byte order, otherwise it must be valid UTF-8.
+    pub fn with_options<TUrl: Into<Vec<u8>>, TScript: Into<Vec<u8>>>(
+        url: TUrl,
+        script: TScript,
+        options: JsParseOptions,
+    ) -> Result<Self, JsError> {
+        let script = script.into();
+        check_encoding(&script, options.attributes)?;
+        JsScript::create(url, script, Some(options))
+    }
+
+    /// Convert a UTF-16 string into a script, parsed with `options`.
+    pub fn from_utf16<TUrl: Into<Vec<u8>>>(
+        url: TUrl,
+        script: &[u16],
+        options: JsParseOptions,
+    ) -> Result<Self, JsError> {
+        let options = options
+            .attributes(options.attributes | JsParseScriptAttributes::ArrayBufferIsUtf16Encoded);
+        let script = script.iter().flat_map(|unit| unit.to_le_bytes()).collect();
+        JsScript::create(url, script, Some(options))
+    }
+
+    /// Convert a static string into a script, parsed with the parse options of the runtime.
+    ///
+    /// The engine references the string directly, so the source is never copied.
+    pub fn from_static<TUrl: Into<Vec<u8>>>(
+        url: TUrl,
+        script: &'static str,
+    ) -> Result<Self, JsError> {
+        let length = c_uint::try_from(script.len()).map_err(|_| JsError::InvalidArgument)?;
+        let mut handle = ptr::null_mut();
 
+        // the engine takes a mutable pointer because array buffers can be handed to scripts. This
+        // one stays private to the `JsScript` and is only read by the parser and the serializer,
+        // so the read-only string is never written to
         let res = unsafe {
             JsCreateExternalArrayBuffer(
-                script as *mut _,
-                size as u32,
+                script.as_ptr() as *mut _,
+                length,
                 None,
                 ptr::null_mut(),
-                &mut source,
+                &mut handle,
             )
         };
+        JsError::assert(res)?;
+
+        JsScript::from_handle(url, handle, None)
+    }
+
+    /// Read a script from a file, parsed with the parse options of the runtime. The path is used
+    /// as the URL of the script.
+    ///
+    /// The file must be UTF-8, or UTF-16 if it starts with a byte order mark. A UTF-8 byte order
+    /// mark is skipped.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, JsSourceError> {
+        let path = path.as_ref();
+        let url = path.to_string_lossy().into_owned();
+        let mut script = fs::read(path)?;
+
+        match script.get(..2) {
+            Some([0xFF, 0xFE]) | Some([0xFE, 0xFF]) => {
+                if script.len() % 2 == 1 {
+                    return Err(JsSourceError::InvalidUtf16);
+                }
+                if script[0] == 0xFE {
+                    script.chunks_exact_mut(2).for_each(|unit| unit.swap(0, 1));
+                }
+                script.drain(..2);
+
+                let options = JsParseOptions::new()
+                    .attributes(JsParseScriptAttributes::ArrayBufferIsUtf16Encoded);
+                return Ok(JsScript::create(url, script, Some(options))?);
+            }
+            _ => {}
+        }
+
+        if script.starts_with(UTF8_BOM) {
+            script.drain(..UTF8_BOM.len());
+        }
+        if let Err(error) = str::from_utf8(&script) {
+            return Err(JsSourceError::InvalidUtf8 {
+                offset: error.valid_up_to(),
+            });
+        }
 
+        Ok(JsScript::create(url, script, None)?)
+    }
+
+    fn create<TUrl: Into<Vec<u8>>>(
+        url: TUrl,
+        mut script: Vec<u8>,
+        options: Option<JsParseOptions>,
+    ) -> Result<Self, JsError> {
+        if let Some(options) = options {
+            let utf16 = options
+                .attributes
+                .contains(JsParseScriptAttributes::ArrayBufferIsUtf16Encoded);
+
+            if options.strict {
+                let directive: Vec<u8> = if utf16 {
+                    STRICT_DIRECTIVE
+                        .encode_utf16()
+                        .flat_map(|unit| unit.to_le_bytes())
+                        .collect()
+                } else {
+                    STRICT_DIRECTIVE.as_bytes().to_vec()
+                };
+                let offset = directive_offset(&script, utf16);
+                script.splice(offset..offset, directive);
+            }
+        }
+
+        // the engine keeps referencing the source after the script ran (e.g. to parse functions
+        // lazily), so the source is released by the engine when the array buffer is collected
+        let handle = external_buffer(script)?;
+
+        JsScript::from_handle(url, handle, options)
+    }
+
+    fn from_handle<TUrl: Into<Vec<u8>>>(
+        url: TUrl,
+        handle: JsValueRef,
+        options: Option<JsParseOptions>,
+    ) -> Result<Self, JsError> {
+        let runtime = JsRuntimeState::current().ok_or(JsError::NoCurrentContext)?;
+        let source_url = JsString::new(url)?;
+
+        // the source is freed once the array buffer is collected, so it has to be kept alive
+        // explicitly while the script is stored on the heap, along with the url
+        let res = unsafe { JsAddRef(source_url.handle, ptr::null_mut()) };
         JsError::assert(res)?;
+        let res = unsafe { JsAddRef(handle, ptr::null_mut()) };
+        if let Err(error) = JsError::assert(res) {
+            unsafe { JsRelease(source_url.handle, ptr::null_mut()) };
+            return Err(error);
+        }
 
         Ok(Self {
-            handle: source,
-            source_url: JsString::new(url)?,
-            raw: script,
+            handle,
+            source_url,
+            options,
+            runtime,
         })
     }
+
+    /// The options the script is parsed with, falling back to the parse options of the runtime.
+    pub(crate) fn parse_options(&self, runtime: &JsRuntimeState) -> JsParseOptions {
+        self.options.unwrap_or(runtime.parse_options)
+    }
+
+    /// The encoding of the source.
+    pub(crate) fn encoding(&self) -> JsParseScriptAttributes {
+        self.options
+            .map_or(JsParseScriptAttributes::None, |options| {
+                options.attributes & JsParseScriptAttributes::ArrayBufferIsUtf16Encoded
+            })
+    }
+
+    /// Parses the script into a `JsCompiledScript` that can be run any number of times without
+    /// parsing the source again.
+    ///
+    /// The context must be the current context. Syntax errors are reported as
+    /// `JsError::SyntaxError`.
+    pub fn compile(&self, context: &JsScriptContext) -> Result<JsCompiledScript, JsError> {
+        context.assert_current()?;
+
+        let options = self.parse_options(&context.runtime);
+        let mut function = ptr::null_mut();
+        let res = unsafe {
+            JsParse(
+                self.handle,
+                options.source_context,
+                self.source_url.handle,
+                options.attributes.bits(),
+                &mut function,
+            )
+        };
+        context.runtime.resume_panic();
+        JsError::assert(res).map_err(|error| self.compile_error(error))?;
+
+        JsCompiledScript::new(function, context.runtime.clone())
+    }
+
+    /// Serializes the parsed form of the script into a buffer that can later be executed with
+    /// `JsSerializedScript` without parsing the source again.
+    ///
+    /// The buffer is only valid for the same version of the engine.
+    pub fn serialize(&self) -> Result<Vec<u8>, JsError> {
+        let mut buffer = ptr::null_mut();
+        let res = unsafe { JsSerialize(self.handle, &mut buffer, self.encoding().bits()) };
+        JsError::assert(res).map_err(|error| self.compile_error(error))?;
+
+        array_buffer_to_vec(buffer)
+    }
+
+    /// Serializes the initial parser state of the script. Unlike `serialize`, the source is still
+    /// needed to run the script with `JsRuntime::run_script_with_parser_state`, but the initial
+    /// scan of the source is skipped.
+    ///
+    /// The buffer is only valid for the same version of the engine.
+    pub fn serialize_parser_state(&self) -> Result<Vec<u8>, JsError> {
+        let mut buffer = ptr::null_mut();
+        let res =
+            unsafe { JsSerializeParserState(self.handle, &mut buffer, self.encoding().bits()) };
+        JsError::assert(res).map_err(|error| self.compile_error(error))?;
+
+        array_buffer_to_vec(buffer)
+    }
+
+    /// Replaces a compilation failure with the syntax error reported by the engine.
+    pub(crate) fn compile_error(&self, error: JsError) -> JsError {
+        if error != JsError::ScriptCompile {
+            return error;
+        }
+
+        let mut metadata = ptr::null_mut();
+        let res = unsafe { JsGetAndClearExceptionWithMetadata(&mut metadata) };
+        if JsError::assert(res).is_err() {
+            return error;
+        }
+
+        let details = || -> Result<JsError, JsError> {
+            let metadata = JsObject { handle: metadata };
+            let property = |name: &str| -> Result<JsValue, JsError> {
+                metadata.get_property(&JsString::new(name)?)
+            };
+            let position = |name: &str| -> Result<u32, JsError> {
+                let value: i32 = JsNumber::try_from(property(name)?)?.try_into()?;
+                Ok(value as u32 + 1)
+            };
+
+            Ok(JsError::SyntaxError {
+                message: JsString::try_from(property("exception")?)?.to_string()?,
+                url: self.source_url.to_string()?,
+                line: position("line")?,
+                column: position("column")?,
+            })
+        };
+
+        details().unwrap_or(error)
+    }
+}
+
+impl Debug for JsScript {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsScript")
+            .field("handle", &self.handle)
+            .field("source_url", &self.source_url)
+            .field("options", &self.options)
+            .finish()
+    }
 }
 
 impl Drop for JsScript {
     fn drop(&mut self) {
-        // release script from memory
-        unsafe {
-            std::mem::drop(CString::from_raw(self.raw));
+        // the source is gone with the runtime
+        if !self.runtime.disposed.get() {
+            unsafe {
+                JsRelease(self.handle, ptr::null_mut());
+                JsRelease(self.source_url.handle, ptr::null_mut());
+            }
+        }
+    }
+}
+
+/// Copies the contents of an array buffer.
+fn array_buffer_to_vec(buffer: JsValueRef) -> Result<Vec<u8>, JsError> {
+    let mut data = ptr::null_mut();
+    let mut length = 0;
+    let res = unsafe { JsGetArrayBufferStorage(buffer, &mut data, &mut length) };
+    JsError::assert(res)?;
+
+    Ok(unsafe { slice::from_raw_parts(data, length as usize) }.to_vec())
+}
+
+/// A parsed script that can be run repeatedly. This is a function value which runs the script
+/// when called.
+pub struct JsCompiledScript {
+    handle: JsValueRef,
+    runtime: Rc<JsRuntimeState>,
+}
+
+impl JsCompiledScript {
+    pub(crate) fn new(handle: JsValueRef, runtime: Rc<JsRuntimeState>) -> Result<Self, JsError> {
+        // the function is stored on the heap, so it has to be kept alive explicitly
+        let res = unsafe { JsAddRef(handle, ptr::null_mut()) };
+        JsError::assert(res)?;
+
+        Ok(Self { handle, runtime })
+    }
+
+    /// Runs the script and returns its result.
+    pub fn run(&self) -> Result<JsValue, JsError> {
+        let mut this = ptr::null_mut();
+        JsError::assert(unsafe { JsGetUndefinedValue(&mut this) })?;
+
+        let mut arguments = [this];
+        let mut result = ptr::null_mut();
+        let res = unsafe {
+            JsCallFunction(
+                self.handle,
+                arguments.as_mut_ptr(),
+                arguments.len() as c_ushort,
+                &mut result,
+            )
+        };
+        self.runtime.resume_panic();
+        JsError::assert(res)?;
+        self.runtime.after_run()?;
+
+        Ok(JsValue { handle: result })
+    }
+}
+
+impl From<&JsCompiledScript> for JsValue {
+    fn from(script: &JsCompiledScript) -> JsValue {
+        JsValue {
+            handle: script.handle,
+        }
+    }
+}
+
+impl Debug for JsCompiledScript {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsCompiledScript")
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl Drop for JsCompiledScript {
+    fn drop(&mut self) {
+        // the function is gone with the runtime
+        if !self.runtime.disposed.get() {
+            unsafe { JsRelease(self.handle, ptr::null_mut()) };
         }
     }
 }
@@ -55,7 +512,7 @@ impl Drop for JsScript {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::context::JsScriptContext;
+    use crate::boolean::JsBoolean;
     use crate::runtime::JsRuntime;
 
     #[test]
@@ -67,4 +524,242 @@ mod tests {
         let script = JsScript::new("hello", "(() => { return 'Hello world'; })()");
         assert!(script.is_ok());
     }
+
+    #[test]
+    fn compile_and_run_script() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        // the compiled script outlives the script it was compiled from
+        let compiled = {
+            let script = JsScript::new("counter", "this.count = (this.count || 0) + 1;").unwrap();
+            script.compile(&context).unwrap()
+        };
+
+        for i in 1..=3 {
+            let result = JsNumber::try_from(compiled.run().unwrap()).unwrap();
+            assert_eq!(result.try_into(), Ok(i));
+        }
+    }
+
+    #[test]
+    fn run_script_after_garbage_collection() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        // the source and url are only referenced from the heap
+        let scripts: Vec<_> = (0..16)
+            .map(|i| Box::new(JsScript::new("test", format!("{} * 2", i)).unwrap()))
+            .collect();
+        let broken = Box::new(JsScript::new("broken.js", "var a = (;").unwrap());
+        runtime.collect_garbage().unwrap();
+
+        for (i, script) in scripts.iter().enumerate() {
+            let result = JsNumber::try_from(runtime.run_script(script).unwrap()).unwrap();
+            assert_eq!(result.try_into(), Ok(i as i32 * 2));
+        }
+        assert!(matches!(
+            broken.compile(&context),
+            Err(JsError::SyntaxError { url, .. }) if url == "broken.js"
+        ));
+    }
+
+    #[test]
+    fn compile_script_with_syntax_error() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new("broken.js", "var a = 1;\nvar b = (;").unwrap();
+        let error = script.compile(&context).unwrap_err();
+        match error {
+            JsError::SyntaxError {
+                url, line, column, ..
+            } => {
+                assert_eq!(url, "broken.js");
+                assert_eq!(line, 2);
+                assert!(column > 1);
+            }
+            error => panic!("unexpected error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn compile_script_without_current_context() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new("test", "1 + 1").unwrap();
+        context.clear_current_context().unwrap();
+
+        let result = script.compile(&context);
+        assert_eq!(result.err(), Some(JsError::NoCurrentContext));
+    }
+
+    #[test]
+    fn run_utf16_script() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let source: Vec<u16> = "'h\u{e9}llo \u{1f600}'".encode_utf16().collect();
+        let script = JsScript::from_utf16("test", &source, JsParseOptions::new()).unwrap();
+        let result = JsString::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(result.to_string(), Ok("h\u{e9}llo \u{1f600}".to_string()));
+
+        let odd = JsScript::with_options(
+            "test",
+            vec![b'1'],
+            JsParseOptions::new().attributes(JsParseScriptAttributes::ArrayBufferIsUtf16Encoded),
+        );
+        assert_eq!(odd.err(), Some(JsError::InvalidArgument));
+    }
+
+    #[test]
+    fn run_strict_script() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let source = "(function () { return this === undefined; })()";
+        let sloppy = JsScript::new("test", source).unwrap();
+        let result = runtime.run_script(&sloppy).unwrap();
+        assert_eq!(JsBoolean::try_from(result).unwrap().try_into(), Ok(false));
+
+        let options = JsParseOptions::new().strict(true);
+        let strict = JsScript::with_options("test", source, options).unwrap();
+        let result = runtime.run_script(&strict).unwrap();
+        assert_eq!(JsBoolean::try_from(result).unwrap().try_into(), Ok(true));
+
+        let source: Vec<u16> = "undeclared = 1;".encode_utf16().collect();
+        let strict = JsScript::from_utf16("test", &source, options).unwrap();
+        assert!(runtime.run_script(&strict).is_err());
+    }
+
+    #[test]
+    fn strict_script_keeps_line_numbers() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let options = JsParseOptions::new().strict(true);
+        let script =
+            JsScript::with_options("broken.js", "var a = 1;\nvar b = (;", options).unwrap();
+        match script.compile(&context).unwrap_err() {
+            JsError::SyntaxError { line, .. } => assert_eq!(line, 2),
+            error => panic!("unexpected error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn insert_strict_directive_after_hashbang() {
+        assert_eq!(directive_offset(b"1 + 1", false), 0);
+        assert_eq!(directive_offset(b"\xEF\xBB\xBF1 + 1", false), 3);
+        assert_eq!(directive_offset(b"#!/usr/bin/env node\n1 + 1", false), 20);
+        assert_eq!(directive_offset(b"\xEF\xBB\xBF#!node", false), 9);
+
+        let utf16: Vec<u8> = "\u{feff}#!node\n1"
+            .encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        assert_eq!(directive_offset(&utf16, true), 16);
+    }
+
+    #[test]
+    fn run_library_code_with_source_context() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let options = JsParseOptions::new().library_code(true).source_context(42);
+        assert_eq!(options.attributes, JsParseScriptAttributes::LibraryCode);
+
+        let script =
+            JsScript::with_options("prelude", "this.prelude = true; 1 + 1", options).unwrap();
+        let result = JsNumber::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(result.try_into(), Ok(2));
+
+        let compiled = script.compile(&context).unwrap();
+        assert!(compiled.run().is_ok());
+    }
+
+    #[test]
+    fn run_static_script() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        static SOURCE: &str = "function add(a, b) { return a + b; } add(40, 2)";
+        let script = JsScript::from_static("static", SOURCE).unwrap();
+        for _ in 0..2 {
+            let result = JsNumber::try_from(runtime.run_script(&script).unwrap()).unwrap();
+            assert_eq!(result.try_into(), Ok(42));
+        }
+    }
+
+    #[test]
+    fn run_script_from_file() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let directory =
+            std::env::temp_dir().join(format!("chakracore-script-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let utf8 = directory.join("utf8.js");
+        fs::write(&utf8, b"\xEF\xBB\xBF'utf-8'").unwrap();
+        let utf16 = directory.join("utf16.js");
+        let mut source = vec![0xFE, 0xFF];
+        source.extend(
+            "'utf-16'"
+                .encode_utf16()
+                .flat_map(|unit| unit.to_be_bytes()),
+        );
+        fs::write(&utf16, source).unwrap();
+
+        for (path, expected) in [(&utf8, "utf-8"), (&utf16, "utf-16")] {
+            let script = JsScript::from_file(path).unwrap();
+            assert_eq!(
+                script.source_url.to_string(),
+                Ok(path.to_string_lossy().into_owned())
+            );
+
+            let result = JsString::try_from(runtime.run_script(&script).unwrap()).unwrap();
+            assert_eq!(result.to_string(), Ok(expected.to_string()));
+        }
+
+        let invalid = directory.join("invalid.js");
+        fs::write(&invalid, b"'abc\xFF'").unwrap();
+        match JsScript::from_file(&invalid) {
+            Err(JsSourceError::InvalidUtf8 { offset }) => assert_eq!(offset, 4),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let missing = JsScript::from_file(directory.join("missing.js"));
+        assert!(matches!(missing, Err(JsSourceError::Io(_))));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn reject_invalid_script() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let invalid_utf8 = JsScript::new("test", vec![b'1', 0xFF]);
+        assert_eq!(invalid_utf8.err(), Some(JsError::InvalidArgument));
+
+        let invalid_url = JsScript::new(vec![b't', 0xFF], "1");
+        assert_eq!(invalid_url.err(), Some(JsError::InvalidArgument));
+
+        // NUL is a valid character in a script
+        let script = JsScript::new("test", "'a\0b'.length").unwrap();
+        let result = JsNumber::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(result.try_into(), Ok(3));
+    }
 }
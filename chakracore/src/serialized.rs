@@ -0,0 +1,399 @@
+use crate::context::JsScriptContext;
+use crate::error::JsError;
+use crate::panic::catch;
+use crate::runtime::{JsRuntime, JsRuntimeState};
+use crate::script::{JsCompiledScript, JsParseScriptAttributes, JsScript};
+use crate::string::JsString;
+use crate::value::JsValue;
+use chakracore_sys::{
+    JsAddRef, JsCreateExternalArrayBuffer, JsParseSerialized, JsRelease, JsRunSerialized,
+    JsSourceContext, JsValueRef,
+};
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::os::raw::c_uint;
+use std::ptr;
+use std::rc::Rc;
+
+type SourceLoader = dyn FnOnce() -> Result<JsScript, JsError>;
+
+/// The source of a serialized script, loaded only if the engine needs it (e.g. for
+/// `Function.prototype.toString`).
+///
+/// The engine may ask for the source as long as functions created from the script are alive. They
+/// keep the serialized buffer alive as well, so the source is released along with the buffer.
+struct JsSerializedSource {
+    loader: RefCell<Option<Box<SourceLoader>>>,
+    script: RefCell<Option<JsScript>>,
+}
+
+impl JsSerializedSource {
+    fn load(&self) -> Result<(JsValueRef, JsParseScriptAttributes), JsError> {
+        if let Some(script) = self.script.borrow().as_ref() {
+            return Ok((script.handle, script.encoding()));
+        }
+
+        let loader = self
+            .loader
+            .borrow_mut()
+            .take()
+            .ok_or(JsError::InvalidArgument)?;
+        let script = loader()?;
+        let loaded = (script.handle, script.encoding());
+        *self.script.borrow_mut() = Some(script);
+
+        Ok(loaded)
+    }
+}
+
+unsafe extern "C" fn load_source(
+    source_context: JsSourceContext,
+    value: *mut JsValueRef,
+    parse_attributes: *mut c_uint,
+) -> bool {
+    let source = &*(source_context as *const JsSerializedSource);
+    let state = JsRuntimeState::current();
+    catch(state.as_deref(), false, || match source.load() {
+        Ok((handle, encoding)) => {
+            *value = handle;
+            *parse_attributes = encoding.bits();
+            true
+        }
+        Err(_) => false,
+    })
+}
+
+/// A serialized script along with its source, released together by the engine.
+struct SerializedBuffer {
+    data: Vec<u8>,
+    source: JsSerializedSource,
+    runtime: Rc<JsRuntimeState>,
+}
+
+/// Releases the owner of an external buffer once the engine no longer references it.
+unsafe extern "C" fn finalize<T>(data: *mut c_void) {
+    let state = JsRuntimeState::current();
+    catch(state.as_deref(), (), || drop(Box::from_raw(data as *mut T)));
+}
+
+/// Hands a serialized buffer over to its runtime once the engine no longer references it. The
+/// source holds handles, which can't be released while the engine is collecting.
+unsafe extern "C" fn finalize_serialized(data: *mut c_void) {
+    let buffer = Box::from_raw(data as *mut SerializedBuffer);
+    let runtime = buffer.runtime.clone();
+    catch(Some(&runtime), (), || {
+        runtime.finalized.borrow_mut().push(buffer);
+    });
+}
+
+/// Hands the bytes of `owner` over to the engine as an external array buffer. The owner is dropped
+/// by `finalize` once the buffer is collected.
+fn external<T, F>(
+    mut owner: Box<T>,
+    bytes: F,
+    finalize: unsafe extern "C" fn(*mut c_void),
+) -> Result<JsValueRef, JsError>
+where
+    F: FnOnce(&mut T) -> &mut [u8],
+{
+    let data = bytes(&mut owner);
+    let size = c_uint::try_from(data.len()).map_err(|_| JsError::InvalidArgument)?;
+    let data = data.as_mut_ptr();
+    let owner = Box::into_raw(owner);
+    let mut handle = ptr::null_mut();
+
+    let res = unsafe {
+        JsCreateExternalArrayBuffer(
+            data as *mut _,
+            size,
+            Some(finalize),
+            owner as *mut _,
+            &mut handle,
+        )
+    };
+
+    if let Err(error) = JsError::assert(res) {
+        drop(unsafe { Box::from_raw(owner) });
+        return Err(error);
+    }
+
+    Ok(handle)
+}
+
+/// Hands a buffer over to the engine as an external array buffer.
+pub(crate) fn external_buffer(buffer: Vec<u8>) -> Result<JsValueRef, JsError> {
+    external(
+        Box::new(buffer),
+        |buffer| buffer.as_mut_slice(),
+        finalize::<Vec<u8>>,
+    )
+}
+
+/// A script serialized with `JsScript::serialize`, which can be executed without parsing the
+/// source.
+///
+/// The engine takes over the buffer when the script is executed, so a serialized script can only
+/// be executed once.
+pub struct JsSerializedScript {
+    buffer: JsValueRef,
+    source_url: JsString,
+    source: *const JsSerializedSource,
+    runtime: Rc<JsRuntimeState>,
+}
+
+impl JsSerializedScript {
+    /// Create a serialized script from a buffer and the script it was serialized from.
+    pub fn new(buffer: Vec<u8>, script: JsScript) -> Result<Self, JsError> {
+        let source_url = JsString::try_from(JsValue {
+            handle: script.source_url.handle,
+        })?;
+
+        JsSerializedScript::create(
+            source_url,
+            buffer,
+            JsSerializedSource {
+                loader: RefCell::new(None),
+                script: RefCell::new(Some(script)),
+            },
+        )
+    }
+
+    /// Create a serialized script from a buffer, loading the source with `loader` only when the
+    /// engine needs it.
+    pub fn with_source_loader<TUrl, F>(
+        url: TUrl,
+        buffer: Vec<u8>,
+        loader: F,
+    ) -> Result<Self, JsError>
+    where
+        TUrl: Into<Vec<u8>>,
+        F: FnOnce() -> Result<JsScript, JsError> + 'static,
+    {
+        JsSerializedScript::create(
+            JsString::new(url)?,
+            buffer,
+            JsSerializedSource {
+                loader: RefCell::new(Some(Box::new(loader))),
+                script: RefCell::new(None),
+            },
+        )
+    }
+
+    fn create(
+        source_url: JsString,
+        buffer: Vec<u8>,
+        source: JsSerializedSource,
+    ) -> Result<Self, JsError> {
+        // don't bother the engine with a buffer that can't possibly be a serialized script
+        if buffer.is_empty() {
+            return Err(JsError::BadSerializedScript);
+        }
+
+        let runtime = JsRuntimeState::current().ok_or(JsError::NoCurrentContext)?;
+        let serialized = Box::new(SerializedBuffer {
+            data: buffer,
+            source,
+            runtime: runtime.clone(),
+        });
+        let source = &serialized.source as *const JsSerializedSource;
+        let handle = external(
+            serialized,
+            |serialized| serialized.data.as_mut_slice(),
+            finalize_serialized,
+        )?;
+
+        // the engine references the buffer once the script is executed, until then it has to be
+        // kept alive explicitly, along with the url
+        let res = unsafe { JsAddRef(handle, ptr::null_mut()) };
+        JsError::assert(res)?;
+        let res = unsafe { JsAddRef(source_url.handle, ptr::null_mut()) };
+        if let Err(error) = JsError::assert(res) {
+            unsafe { JsRelease(handle, ptr::null_mut()) };
+            return Err(error);
+        }
+
+        Ok(Self {
+            buffer: handle,
+            source_url,
+            source,
+            runtime,
+        })
+    }
+
+    fn source_context(&self) -> JsSourceContext {
+        self.source as JsSourceContext
+    }
+
+    /// Parses the serialized script into a `JsCompiledScript` that can be run any number of
+    /// times.
+    ///
+    /// The context must be the current context. A buffer serialized by a different version of the
+    /// engine is reported as `JsError::BadSerializedScript`.
+    pub fn compile(self, context: &JsScriptContext) -> Result<JsCompiledScript, JsError> {
+        context.assert_current()?;
+
+        let mut function = ptr::null_mut();
+        let res = unsafe {
+            JsParseSerialized(
+                self.buffer,
+                Some(load_source),
+                self.source_context(),
+                self.source_url.handle,
+                &mut function,
+            )
+        };
+        context.runtime.resume_panic();
+        JsError::assert(res)?;
+
+        JsCompiledScript::new(function, context.runtime.clone())
+    }
+
+    pub(crate) fn run(self, runtime: &mut JsRuntime) -> Result<JsValue, JsError> {
+        let mut result = ptr::null_mut();
+        let res = unsafe {
+            JsRunSerialized(
+                self.buffer,
+                Some(load_source),
+                self.source_context(),
+                self.source_url.handle,
+                &mut result,
+            )
+        };
+        runtime.state.resume_panic();
+        JsError::assert(res)?;
+
+        Ok(JsValue { handle: result })
+    }
+}
+
+impl Drop for JsSerializedScript {
+    fn drop(&mut self) {
+        // the buffer is gone with the runtime
+        if !self.runtime.disposed.get() {
+            unsafe {
+                JsRelease(self.buffer, ptr::null_mut());
+                JsRelease(self.source_url.handle, ptr::null_mut());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::number::JsNumber;
+
+    fn serialize(source: &str) -> Vec<u8> {
+        let script = JsScript::new("test", source).unwrap();
+        script.serialize().unwrap()
+    }
+
+    #[test]
+    fn run_serialized_script() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let source = "(() => { return 40 + 2; })()";
+        let buffer = serialize(source);
+
+        let script = JsScript::new("test", source).unwrap();
+        let serialized = JsSerializedScript::new(buffer, script).unwrap();
+        let result = JsNumber::try_from(runtime.run_serialized(serialized).unwrap()).unwrap();
+        assert_eq!(result.try_into(), Ok(42));
+    }
+
+    #[test]
+    fn run_serialized_script_in_another_runtime() {
+        let source = "function add(a, b) { return a + b; } add.toString()";
+        let buffer = {
+            let mut runtime = JsRuntime::new().unwrap();
+            let mut context = JsScriptContext::new(&mut runtime).unwrap();
+            context.set_current_context().unwrap();
+            serialize(source)
+        };
+
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let serialized = JsSerializedScript::with_source_loader("test", buffer, move || {
+            JsScript::new("test", source)
+        })
+        .unwrap();
+        let result = JsString::try_from(runtime.run_serialized(serialized).unwrap()).unwrap();
+        assert_eq!(
+            result.to_string(),
+            Ok("function add(a, b) { return a + b; }".to_string())
+        );
+    }
+
+    #[test]
+    fn load_source_after_garbage_collection() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let source = "function add(a, b) { return a + b; }";
+        let buffer = serialize(source);
+
+        for _ in 0..3 {
+            let serialized =
+                JsSerializedScript::with_source_loader("test", buffer.clone(), move || {
+                    JsScript::new("test", source)
+                })
+                .unwrap();
+            runtime.run_serialized(serialized).unwrap();
+            runtime.collect_garbage().unwrap();
+
+            // collected sources are dropped once the collection is over
+            assert!(runtime.state.finalized.borrow().is_empty());
+        }
+
+        // the function still references the source of the last script
+        let script = JsScript::new("test", "add.toString()").unwrap();
+        let result = JsString::try_from(runtime.run_script(&script).unwrap()).unwrap();
+        assert_eq!(result.to_string(), Ok(source.to_string()));
+    }
+
+    #[test]
+    fn compile_serialized_script() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let source = "this.count = (this.count || 0) + 1;";
+        let buffer = serialize(source);
+
+        let serialized = JsSerializedScript::with_source_loader("test", buffer, move || {
+            JsScript::new("test", source)
+        })
+        .unwrap();
+        let compiled = serialized.compile(&context).unwrap();
+
+        for i in 1..=3 {
+            let result = JsNumber::try_from(compiled.run().unwrap()).unwrap();
+            assert_eq!(result.try_into(), Ok(i));
+        }
+    }
+
+    #[test]
+    fn run_bad_serialized_script() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let source = "1 + 1";
+        let mut buffer = serialize(source);
+        buffer.iter_mut().take(16).for_each(|byte| *byte = !*byte);
+
+        let script = JsScript::new("test", source).unwrap();
+        let serialized = JsSerializedScript::new(buffer, script).unwrap();
+        let result = runtime.run_serialized(serialized);
+        assert_eq!(result.err(), Some(JsError::BadSerializedScript));
+
+        let script = JsScript::new("test", source).unwrap();
+        let empty = JsSerializedScript::new(Vec::new(), script);
+        assert_eq!(empty.err(), Some(JsError::BadSerializedScript));
+    }
+}
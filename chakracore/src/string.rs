@@ -1,48 +1,188 @@
 use crate::error::JsError;
+use crate::runtime::JsRuntimeState;
 use crate::value::JsValue;
-use chakracore_sys::{JsConvertValueToString, JsCopyString, JsCreateString, JsValueRef};
-use std::ffi::{CStr, CString};
+use chakracore_sys::{
+    JsConvertValueToString, JsCopyString, JsCopyStringOneByte, JsCopyStringUtf16, JsCreateString,
+    JsCreateStringUtf16, JsGetStringLength, JsValueRef,
+};
 use std::fmt::{Debug, Formatter};
+use std::os::raw::{c_char, c_int};
 use std::ptr;
+use std::str::{self, FromStr};
 
 pub struct JsString {
     pub(crate) handle: JsValueRef,
 }
 
 impl JsString {
-    /// Create a `JsString`
+    /// Create a `JsString`. The value must be valid UTF-8.
     pub fn new<T: Into<Vec<u8>>>(value: T) -> Result<Self, JsError> {
-        let string = CString::new(value).unwrap();
+        let value = value.into();
+        str::from_utf8(&value)
+            .map_err(|_| JsError::InvalidArgument)?
+            .parse()
+    }
 
+    /// Create a `JsString` from UTF-16 code units. Lone surrogates are kept as is.
+    pub fn from_utf16(value: &[u16]) -> Result<Self, JsError> {
         let mut handle = ptr::null_mut();
-        let res =
-            unsafe { JsCreateString(string.as_ptr(), string.as_bytes().len() as u64, &mut handle) };
+        let res = unsafe { JsCreateStringUtf16(value.as_ptr(), value.len() as u64, &mut handle) };
         JsError::assert(res)?;
 
         Ok(Self { handle })
     }
 
-    /// Convert to a String
-    pub fn to_string(&self) -> Result<String, JsError> {
-        // get size of buffer
+    /// The length of the string in UTF-16 code units.
+    pub fn len(&self) -> Result<usize, JsError> {
         let mut length = 0;
-        JsError::assert(unsafe { JsCopyString(self.handle, ptr::null_mut(), 0, &mut length) })?;
+        let res = unsafe { JsGetStringLength(self.handle, &mut length) };
+        JsError::assert(res)?;
+
+        Ok(length as usize)
+    }
+
+    /// Whether the string is empty.
+    pub fn is_empty(&self) -> Result<bool, JsError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Convert to a String. Lone surrogates can't be represented in UTF-8, so they are replaced by
+    /// U+FFFD; use `to_utf16` to get the exact contents of the string.
+    pub fn to_string(&self) -> Result<String, JsError> {
+        let mut result = String::new();
+        self.write_to(&mut result)?;
+        Ok(result)
+    }
+
+    /// Appends the string to `output`, copying it only once. Lone surrogates are replaced by
+    /// U+FFFD.
+    ///
+    /// Up to three bytes per UTF-16 code unit are reserved in `output`, so reusing the same
+    /// `String` (or a `JsStringBuffer`) avoids allocating for each conversion.
+    pub fn write_to(&self, output: &mut String) -> Result<(), JsError> {
+        // a UTF-16 code unit never takes more than three bytes in UTF-8, which saves asking the
+        // engine for the exact length
+        let capacity = self.len()? * 3;
+        let start = output.len();
+
+        // the bytes are only appended once they are known to be valid UTF-8
+        let buffer = unsafe { output.as_mut_vec() };
+        buffer.reserve(capacity);
 
-        // copy to buffer
-        let total_length = length + 1;
-        let mut buffer: Vec<u8> = vec![0; total_length.try_into().unwrap()];
+        let mut written = 0;
         let res = unsafe {
             JsCopyString(
                 self.handle,
-                buffer.as_mut_ptr() as *mut i8,
-                total_length,
-                ptr::null_mut(),
+                buffer.as_mut_ptr().add(start) as *mut c_char,
+                capacity as u64,
+                &mut written,
+            )
+        };
+        JsError::assert(res)?;
+        unsafe { buffer.set_len(start + written as usize) };
+
+        if let Err(error) = str::from_utf8(&buffer[start..]) {
+            let valid = start + error.valid_up_to();
+            let lossy = String::from_utf8_lossy(&buffer[valid..]).into_owned();
+            buffer.truncate(valid);
+            buffer.extend_from_slice(lossy.as_bytes());
+        }
+
+        Ok(())
+    }
+
+    /// Convert to UTF-16 code units, including lone surrogates.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn to_utf16(&self) -> Result<Vec<u16>, JsError> {
+        let length = self.len()?;
+        let mut buffer: Vec<u16> = vec![0; length];
+        let mut written = 0;
+        let res = unsafe {
+            JsCopyStringUtf16(
+                self.handle,
+                0,
+                length as c_int,
+                buffer.as_mut_ptr(),
+                &mut written,
+            )
+        };
+        JsError::assert(res)?;
+        buffer.truncate(written as usize);
+
+        Ok(buffer)
+    }
+
+    /// Convert to Latin-1. Like the engine, characters outside of Latin-1 are truncated to their
+    /// lower byte.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn to_latin1(&self) -> Result<Vec<u8>, JsError> {
+        let length = self.len()?;
+        let mut buffer: Vec<u8> = vec![0; length];
+        let mut written = 0;
+        let res = unsafe {
+            JsCopyStringOneByte(
+                self.handle,
+                0,
+                length as c_int,
+                buffer.as_mut_ptr() as *mut c_char,
+                &mut written,
             )
         };
         JsError::assert(res)?;
+        buffer.truncate(written as usize);
+
+        Ok(buffer)
+    }
+}
+
+impl FromStr for JsString {
+    type Err = JsError;
+
+    /// Create a `JsString` without any intermediate copies.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut handle = ptr::null_mut();
+        let res = unsafe {
+            JsCreateString(
+                value.as_ptr() as *const c_char,
+                value.len() as u64,
+                &mut handle,
+            )
+        };
+        JsError::assert(res)?;
+
+        Ok(Self { handle })
+    }
+}
+
+/// A reusable buffer for converting `JsString`s to Rust strings without allocating each time.
+#[derive(Debug, Default)]
+pub struct JsStringBuffer {
+    buffer: String,
+}
 
-        let result_str = CStr::from_bytes_with_nul(buffer.as_slice()).unwrap();
-        Ok(result_str.to_owned().into_string().unwrap())
+impl JsStringBuffer {
+    /// Create an empty buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a buffer that can hold strings of up to `capacity` bytes without allocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffer: String::with_capacity(capacity),
+        }
+    }
+
+    /// Replaces the contents of the buffer with `string`, returning the contents.
+    pub fn read(&mut self, string: &JsString) -> Result<&str, JsError> {
+        self.buffer.clear();
+        string.write_to(&mut self.buffer)?;
+        Ok(&self.buffer)
+    }
+
+    /// The contents of the buffer.
+    pub fn as_str(&self) -> &str {
+        &self.buffer
     }
 }
 
@@ -51,7 +191,9 @@ impl TryFrom<JsValue> for JsString {
 
     fn try_from(value: JsValue) -> Result<Self, Self::Error> {
         let mut result = ptr::null_mut();
+        // `valueOf` and `toString` may call back into Rust
         let res = unsafe { JsConvertValueToString(value.handle, &mut result) };
+        JsRuntimeState::resume_current_panic();
         JsError::assert(res)?;
 
         Ok(JsString { handle: result })
@@ -103,4 +245,91 @@ mod tests {
         assert!(!js_string.handle.is_null());
         assert_eq!(js_string.to_string(), Ok("hello world!".to_string()));
     }
+
+    #[test]
+    fn create_and_get_utf16_string() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let utf16: Vec<u16> = "h\u{e9}llo \u{1f600}".encode_utf16().collect();
+        let js_string = JsString::from_utf16(&utf16).unwrap();
+        assert_eq!(js_string.len(), Ok(9));
+        assert_eq!(js_string.is_empty(), Ok(false));
+        assert_eq!(js_string.to_utf16(), Ok(utf16));
+        assert_eq!(
+            js_string.to_string(),
+            Ok("h\u{e9}llo \u{1f600}".to_string())
+        );
+
+        let empty = JsString::from_utf16(&[]).unwrap();
+        assert_eq!(empty.is_empty(), Ok(true));
+        assert_eq!(empty.to_utf16(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn keep_lone_surrogates() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let utf16 = [0x61, 0xD800, 0x62];
+        let js_string = JsString::from_utf16(&utf16).unwrap();
+        assert_eq!(js_string.to_utf16(), Ok(utf16.to_vec()));
+        assert_eq!(js_string.to_string(), Ok("a\u{fffd}b".to_string()));
+    }
+
+    #[test]
+    fn get_latin1_string() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let js_string = JsString::new("caf\u{e9}").unwrap();
+        assert_eq!(js_string.len(), Ok(4));
+        assert_eq!(js_string.to_latin1(), Ok(b"caf\xE9".to_vec()));
+    }
+
+    #[test]
+    fn create_string_with_nul() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let js_string: JsString = "a\0b".parse().unwrap();
+        assert_eq!(js_string.len(), Ok(3));
+        assert_eq!(js_string.to_string(), Ok("a\0b".to_string()));
+
+        let invalid = JsString::new(vec![b'a', 0xFF]);
+        assert_eq!(invalid.err(), Some(JsError::InvalidArgument));
+    }
+
+    #[test]
+    fn write_string_to_buffer() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let mut output = String::from("> ");
+        "h\u{e9}llo"
+            .parse::<JsString>()
+            .unwrap()
+            .write_to(&mut output)
+            .unwrap();
+        JsString::from_utf16(&[0x20, 0xD83D, 0xDE00, 0xD800])
+            .unwrap()
+            .write_to(&mut output)
+            .unwrap();
+        assert_eq!(output, "> h\u{e9}llo \u{1f600}\u{fffd}");
+
+        let mut buffer = JsStringBuffer::with_capacity(64);
+        let long = "a longer string".parse::<JsString>().unwrap();
+        let short = "short".parse::<JsString>().unwrap();
+        assert_eq!(buffer.read(&long), Ok("a longer string"));
+        assert_eq!(buffer.read(&short), Ok("short"));
+        assert_eq!(buffer.as_str(), "short");
+
+        let empty = "".parse::<JsString>().unwrap();
+        assert_eq!(buffer.read(&empty), Ok(""));
+    }
 }
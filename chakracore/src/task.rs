@@ -0,0 +1,105 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Wake, Waker};
+use std::thread::{self, Thread};
+
+/// Wakes the thread of a runtime waiting in `JsRuntime::block_on`.
+pub(crate) struct Signal {
+    notified: AtomicBool,
+    thread: Thread,
+}
+
+impl Signal {
+    /// A signal for the current thread.
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            notified: AtomicBool::new(false),
+            thread: thread::current(),
+        })
+    }
+
+    /// Parks the thread until the signal is notified, returning immediately if it was notified
+    /// since the last wait.
+    pub(crate) fn wait(&self) {
+        while !self.notified.swap(false, Ordering::SeqCst) {
+            thread::park();
+        }
+    }
+
+    fn notify(&self) {
+        self.notified.store(true, Ordering::SeqCst);
+        self.thread.unpark();
+    }
+}
+
+/// Tracks whether a future has to be polled again. Wakers may be sent to other threads (e.g. by
+/// an IPC client), which notify the signal of the runtime.
+pub(crate) struct Wakeup {
+    woken: AtomicBool,
+    signal: Arc<Signal>,
+}
+
+impl Wakeup {
+    /// A wakeup that starts out woken, so the future is polled a first time.
+    pub(crate) fn new(signal: Arc<Signal>) -> Arc<Self> {
+        Arc::new(Self {
+            woken: AtomicBool::new(true),
+            signal,
+        })
+    }
+
+    pub(crate) fn is_woken(&self) -> bool {
+        self.woken.load(Ordering::SeqCst)
+    }
+
+    /// Clears the wakeup, returning whether it was woken.
+    pub(crate) fn take(&self) -> bool {
+        self.woken.swap(false, Ordering::SeqCst)
+    }
+}
+
+impl Wake for Wakeup {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+        self.signal.notify();
+    }
+}
+
+/// A future spawned on the runtime, polled along with the job queue when woken.
+pub(crate) struct LocalTask {
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    wakeup: Arc<Wakeup>,
+}
+
+impl LocalTask {
+    pub(crate) fn new<F>(future: F, signal: Arc<Signal>) -> Self
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        Self {
+            future: Box::pin(future),
+            wakeup: Wakeup::new(signal),
+        }
+    }
+
+    pub(crate) fn is_woken(&self) -> bool {
+        self.wakeup.is_woken()
+    }
+
+    /// Polls the task if it was woken, returning whether it completed.
+    pub(crate) fn poll(&mut self) -> bool {
+        if !self.wakeup.take() {
+            return false;
+        }
+
+        let waker = Waker::from(self.wakeup.clone());
+        let mut cx = Context::from_waker(&waker);
+        self.future.as_mut().poll(&mut cx).is_ready()
+    }
+}
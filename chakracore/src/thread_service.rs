@@ -0,0 +1,206 @@
+use crate::error::JsError;
+use crate::panic::catch;
+use crate::runtime::JsRuntimeState;
+use chakracore_sys::{JsBackgroundWorkItemCallback, JsThreadServiceCallback};
+use std::ffi::c_void;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// The most thread services that can be in use at once.
+const SLOTS: usize = 32;
+
+type Slot = Option<(Arc<dyn JsThreadService>, usize)>;
+
+/// The thread services in use, along with the number of runtimes using them.
+/// `JsThreadServiceCallback` does not carry any host state, so each service is registered in a
+/// slot with a callback of its own.
+static SERVICES: Mutex<[Slot; SLOTS]> = Mutex::new([const { None }; SLOTS]);
+
+macro_rules! callbacks {
+    ($($slot:literal)*) => {
+        [$(Some(dispatch::<$slot> as unsafe extern "C" fn(_, _) -> bool)),*]
+    };
+}
+
+/// The callback of each slot.
+const CALLBACKS: [JsThreadServiceCallback; SLOTS] = callbacks!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+);
+
+/// A background work item (such as concurrent garbage collection or JIT compilation) scheduled by
+/// the runtime.
+#[derive(Debug)]
+pub struct JsBackgroundWork {
+    callback: JsBackgroundWorkItemCallback,
+    state: *mut c_void,
+}
+
+// The work item is meant to be executed on a background thread of the host's choice.
+unsafe impl Send for JsBackgroundWork {}
+
+impl JsBackgroundWork {
+    /// Runs the work item on the current thread.
+    pub fn run(self) {
+        if let Some(callback) = self.callback {
+            unsafe { callback(self.state) };
+        }
+    }
+}
+
+/// A host provided executor for background work items.
+///
+/// Runtimes created with a thread service don't spawn their own background threads, which allows
+/// the host to share a bounded pool of threads across many runtimes.
+pub trait JsThreadService: Send + Sync {
+    /// Schedules a work item. The work item should begin executing immediately, in which case
+    /// `true` is returned. Returning `false` hands the work item back to the runtime, which will
+    /// run it in-thread.
+    fn submit(&self, work: JsBackgroundWork) -> bool;
+}
+
+impl<F> JsThreadService for F
+where
+    F: Fn(JsBackgroundWork) -> bool + Send + Sync,
+{
+    fn submit(&self, work: JsBackgroundWork) -> bool {
+        self(work)
+    }
+}
+
+/// Registers the thread service of a runtime about to be created, returning the slot of the
+/// service. Runtimes sharing a service share its slot.
+pub(crate) fn register(service: &Arc<dyn JsThreadService>) -> Result<usize, JsError> {
+    let mut services = SERVICES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(slot) = services
+        .iter()
+        .position(|slot| matches!(slot, Some((existing, _)) if ptr_eq(existing, service)))
+    {
+        if let Some((_, count)) = &mut services[slot] {
+            *count += 1;
+        }
+        return Ok(slot);
+    }
+
+    let slot = services
+        .iter()
+        .position(Option::is_none)
+        .ok_or(JsError::TooManyThreadServices)?;
+    services[slot] = Some((service.clone(), 1));
+    Ok(slot)
+}
+
+/// The callback dispatching the work of the runtimes registered in `slot`.
+pub(crate) fn callback(slot: usize) -> JsThreadServiceCallback {
+    CALLBACKS[slot]
+}
+
+/// Releases the registration of a runtime that was created with a thread service.
+pub(crate) fn unregister(slot: usize) {
+    let mut services = SERVICES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((_, count)) = &mut services[slot] {
+        *count -= 1;
+        if *count == 0 {
+            services[slot] = None;
+        }
+    }
+}
+
+fn ptr_eq(a: &Arc<dyn JsThreadService>, b: &Arc<dyn JsThreadService>) -> bool {
+    Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const ()
+}
+
+unsafe extern "C" fn dispatch<const SLOT: usize>(
+    callback: JsBackgroundWorkItemCallback,
+    callback_state: *mut c_void,
+) -> bool {
+    let service = SERVICES.lock().unwrap_or_else(PoisonError::into_inner)[SLOT]
+        .as_ref()
+        .map(|(service, _)| service.clone());
+
+    match service {
+        Some(service) => {
+            let state = JsRuntimeState::current();
+            catch(state.as_deref(), false, || {
+                service.submit(JsBackgroundWork {
+                    callback,
+                    state: callback_state,
+                })
+            })
+        }
+        // the runtime runs the work itself
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::JsScriptContext;
+    use crate::runtime::JsRuntime;
+    use crate::script::JsScript;
+
+    #[test]
+    fn run_script_with_thread_service() {
+        let service = |work: JsBackgroundWork| {
+            std::thread::spawn(move || work.run());
+            true
+        };
+
+        let mut runtime = JsRuntime::builder()
+            .thread_service(Arc::new(service))
+            .build()
+            .unwrap();
+        let mut context = JsScriptContext::new(&mut runtime).unwrap();
+        context.set_current_context().unwrap();
+
+        let script = JsScript::new(
+            "test",
+            "(() => { var a = []; for (var i = 0; i < 100000; i++) { a.push({ i }); } })()",
+        )
+        .unwrap();
+        runtime.run_script(&script).unwrap();
+    }
+
+    #[test]
+    fn share_thread_service_between_runtimes() {
+        let service: Arc<dyn JsThreadService> = Arc::new(|work: JsBackgroundWork| {
+            work.run();
+            true
+        });
+
+        let first = JsRuntime::builder().thread_service(service.clone()).build();
+        let second = JsRuntime::builder().thread_service(service).build();
+        assert!(first.is_ok());
+        assert!(second.is_ok());
+    }
+
+    #[test]
+    fn use_different_thread_services_on_same_thread() {
+        let spawn = |work: JsBackgroundWork| {
+            std::thread::spawn(move || work.run());
+            true
+        };
+        let run_inline = |work: JsBackgroundWork| {
+            work.run();
+            true
+        };
+
+        let runtimes = [
+            JsRuntime::builder().thread_service(Arc::new(spawn)).build(),
+            JsRuntime::builder()
+                .thread_service(Arc::new(run_inline))
+                .build(),
+        ];
+        for runtime in runtimes {
+            let mut runtime = runtime.unwrap();
+            let mut context = JsScriptContext::new(&mut runtime).unwrap();
+            context.set_current_context().unwrap();
+
+            let script = JsScript::new(
+                "test",
+                "(() => { var a = []; for (var i = 0; i < 100000; i++) { a.push({ i }); } })()",
+            )
+            .unwrap();
+            runtime.run_script(&script).unwrap();
+        }
+    }
+}
@@ -1,4 +1,6 @@
+use crate::error::JsError;
 use crate::value::JsValue;
+use chakracore_sys::JsGetUndefinedValue;
 use std::ptr;
 
 impl From<()> for JsValue {
@@ -8,3 +10,12 @@ impl From<()> for JsValue {
         }
     }
 }
+
+/// The `undefined` value of the current context.
+pub(crate) fn undefined() -> Result<JsValue, JsError> {
+    let mut handle = ptr::null_mut();
+    let res = unsafe { JsGetUndefinedValue(&mut handle) };
+    JsError::assert(res)?;
+
+    Ok(JsValue { handle })
+}
@@ -1,5 +1,7 @@
+use crate::context::JsContextHandle;
 use crate::error::JsError;
-use chakracore_sys::{JsGetValueType, JsValueRef};
+use chakracore_sys::{JsGetContextOfObject, JsGetValueType, JsValueRef};
+use std::ptr;
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum JsType {
@@ -45,6 +47,16 @@ impl JsValue {
             _ => unreachable!(),
         })
     }
+
+    /// The context the value was created in. Only objects belong to a context, other values
+    /// fail with `JsError::ArgumentNotObject`.
+    pub fn owning_context(&self) -> Result<JsContextHandle, JsError> {
+        let mut handle = ptr::null_mut();
+        let res = unsafe { JsGetContextOfObject(self.handle, &mut handle) };
+        JsError::assert(res)?;
+
+        Ok(JsContextHandle { handle })
+    }
 }
 
 #[cfg(test)]
@@ -53,6 +65,7 @@ mod tests {
     use crate::boolean::JsBoolean;
     use crate::context::JsScriptContext;
     use crate::number::JsNumber;
+    use crate::object::JsObject;
     use crate::runtime::JsRuntime;
 
     #[test]
@@ -72,4 +85,23 @@ mod tests {
         let value: JsValue = bool.into();
         assert_eq!(value.get_type(), Ok(JsType::Boolean));
     }
+
+    #[test]
+    fn get_owning_context() {
+        let mut runtime = JsRuntime::new().unwrap();
+        let first = JsScriptContext::new(&mut runtime).unwrap();
+        let second = JsScriptContext::new(&mut runtime).unwrap();
+
+        let object: JsValue = {
+            let _scope = first.enter().unwrap();
+            JsObject::new().unwrap().into()
+        };
+
+        let _scope = second.enter().unwrap();
+        assert_eq!(object.owning_context(), Ok(first.handle()));
+        assert_ne!(object.owning_context(), Ok(second.handle()));
+
+        let number: JsValue = JsNumber::from(42).into();
+        assert_eq!(number.owning_context(), Err(JsError::ArgumentNotObject));
+    }
 }